@@ -0,0 +1,752 @@
+//! Configuration of the [`MountWatcher`].
+
+use std::{
+    convert::Infallible, fmt, marker::PhantomData, os::fd::OwnedFd, path::PathBuf, time::Duration,
+};
+
+use crate::{
+    callback::{try_coalesce, CoalesceInitial},
+    channel::MountEventReceiver,
+    fanotify::FanotifyTable,
+    filter::MountFilter,
+    fstab::FSTAB_PATH,
+    mount::{LinuxMount, MountEntry},
+    mountinfo::MountInfo,
+    statmount::StatmountTable,
+    table::MountTableSource,
+    watch::{watch_mounts, CallbackError, ErrorPolicy, SetupError, WatchError},
+    MountEvent, MountWatcher, WatchControl,
+};
+
+/// Builder for a [`MountWatcher`] with a custom configuration.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use mount_watcher::{MountWatcher, WatchControl};
+/// use mount_watcher::callback::CoalesceInitial;
+/// use mount_watcher::filter::MountFilter;
+///
+/// let watch = MountWatcher::builder()
+///     .thread_name("mount-watcher")
+///     .initial_event(false)
+///     .coalesce(Duration::from_secs(1), CoalesceInitial::PassImmediately)
+///     .filter(MountFilter::new().mount_point_prefix("/mnt"))
+///     .build(|event| {
+///         println!("new mounts in /mnt: {:?}", event.mounted);
+///         WatchControl::Continue
+///     })
+///     .unwrap();
+/// ```
+///
+/// The type parameters are the type of the reported entries, and whether a source has been
+/// chosen, see [`NoSource`] and [`WithSource`].
+#[derive(Debug)]
+pub struct MountWatcherBuilder<M = LinuxMount, S = NoSource> {
+    pub(crate) table: Option<TableLocation>,
+    pub(crate) thread_name: Option<String>,
+    pub(crate) stack_size: Option<usize>,
+    pub(crate) initial_event: bool,
+    pub(crate) coalesce: Option<(Duration, CoalesceInitial)>,
+    pub(crate) filter: Option<MountFilter>,
+    pub(crate) backing_path: Option<PathBuf>,
+    pub(crate) error_policy: ErrorPolicy,
+    pub(crate) error_hook: Option<ErrorHook>,
+    pub(crate) catch_panics: bool,
+    pub(crate) source: Option<Box<dyn MountTableSource<M>>>,
+    pub(crate) fstab: Option<PathBuf>,
+    entry: PhantomData<fn() -> M>,
+    state: PhantomData<S>,
+}
+
+/// State of a [`MountWatcherBuilder`] that reads the kernel's mount table.
+#[derive(Debug)]
+pub enum NoSource {}
+
+/// State of a [`MountWatcherBuilder`] after [`source`](MountWatcherBuilder::source),
+/// [`statmount`](MountWatcherBuilder::statmount) or [`fanotify`](MountWatcherBuilder::fanotify).
+///
+/// Such a builder has no [`mountinfo`](MountWatcherBuilder::mountinfo) method, since the
+/// source gives another type of entries: call it before choosing the source.
+///
+/// ```compile_fail
+/// use mount_watcher::{table::FakeMountTable, MountWatcher};
+///
+/// let table = FakeMountTable::new().unwrap();
+/// let builder = MountWatcher::builder().source(table).mountinfo();
+/// ```
+#[derive(Debug)]
+pub enum WithSource {}
+
+/// Mount table to watch, when it is not the default one.
+#[derive(Debug)]
+pub(crate) enum TableLocation {
+    Path(PathBuf),
+    Pid(u32),
+    Namespace(OwnedFd),
+}
+
+/// Function called when the background thread encounters an error.
+pub(crate) struct ErrorHook(pub(crate) Box<dyn FnMut(&WatchError) + Send>);
+
+impl fmt::Debug for ErrorHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ErrorHook")
+    }
+}
+
+impl MountWatcher {
+    /// Returns a builder to configure the `MountWatcher`.
+    pub fn builder() -> MountWatcherBuilder {
+        MountWatcherBuilder::new()
+    }
+}
+
+impl Default for MountWatcherBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MountWatcherBuilder {
+    /// Creates a builder with the default configuration, which watches `/proc/mounts`.
+    pub fn new() -> Self {
+        Self {
+            table: None,
+            thread_name: None,
+            stack_size: None,
+            initial_event: true,
+            coalesce: None,
+            filter: None,
+            backing_path: None,
+            error_policy: ErrorPolicy::Stop,
+            error_hook: None,
+            catch_panics: false,
+            source: None,
+            fstab: None,
+            entry: PhantomData,
+            state: PhantomData,
+        }
+    }
+
+    /// Watches a table in the mountinfo format, `/proc/self/mountinfo` by default.
+    ///
+    /// The events will contain [`MountInfo`] entries, see [`MountWatcher::new_mountinfo`].
+    /// A [`table_path`](MountWatcherBuilder::table_path) given before is discarded, since it
+    /// points to a table in the `/proc/mounts` format: set it after this call.
+    pub fn mountinfo(self) -> MountWatcherBuilder<MountInfo> {
+        let table = match self.table {
+            Some(TableLocation::Path(_)) => None,
+            table => table,
+        };
+        MountWatcherBuilder {
+            table,
+            thread_name: self.thread_name,
+            stack_size: self.stack_size,
+            initial_event: self.initial_event,
+            coalesce: self.coalesce,
+            filter: self.filter,
+            backing_path: self.backing_path,
+            error_policy: self.error_policy,
+            error_hook: self.error_hook,
+            catch_panics: self.catch_panics,
+            source: None,
+            fstab: self.fstab,
+            entry: PhantomData,
+            state: PhantomData,
+        }
+    }
+}
+
+impl<M: MountEntry, S> MountWatcherBuilder<M, S> {
+    /// Sets the path of the mount table to watch.
+    ///
+    /// By default, this is `/proc/mounts`, or `/proc/self/mountinfo` for [`mountinfo`](MountWatcherBuilder::mountinfo).
+    /// The file must support `epoll` like these files do, for instance `/proc/<pid>/mounts`.
+    pub fn table_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.table = Some(TableLocation::Path(path.into()));
+        self
+    }
+
+    /// Watches the mounts of another process, for instance the init process of a container.
+    ///
+    /// This reads `/proc/<pid>/mounts`, or `/proc/<pid>/mountinfo` for [`mountinfo`](Self::mountinfo).
+    /// When the process exits, the watcher stops with an error, see [`WatchError::exited_pid`].
+    /// This replaces the [`table_path`](Self::table_path).
+    pub fn pid(mut self, pid: u32) -> Self {
+        self.table = Some(TableLocation::Pid(pid));
+        self
+    }
+
+    /// Watches the mounts of a mount namespace, given a file descriptor such as `/proc/<pid>/ns/mnt`.
+    ///
+    /// Unlike [`pid`](Self::pid), this keeps the namespace alive: the watcher runs until
+    /// it is stopped, even if all the processes of the namespace have exited.
+    /// It requires `CAP_SYS_ADMIN`, see [`ProcMountTable::for_namespace`](crate::table::ProcMountTable::for_namespace).
+    /// This replaces the [`table_path`](Self::table_path).
+    pub fn mount_namespace(mut self, ns: impl Into<OwnedFd>) -> Self {
+        self.table = Some(TableLocation::Namespace(ns.into()));
+        self
+    }
+
+    /// Reads the mounts from `source` instead of the kernel's mount table.
+    ///
+    /// This is mostly useful in tests, with a [`FakeMountTable`](crate::table::FakeMountTable).
+    /// The [`table_path`](Self::table_path), [`pid`](Self::pid) and [`mount_namespace`](Self::mount_namespace)
+    /// are ignored.
+    pub fn source(self, source: impl MountTableSource<M>) -> MountWatcherBuilder<M, WithSource> {
+        self.with_source(Some(Box::new(source)))
+    }
+
+    /// Gets the mounts with the `listmount` and `statmount` syscalls, instead of parsing the
+    /// mount table, if the kernel supports them. Otherwise, the mount table is used.
+    ///
+    /// The events are the same, see [`StatmountTable`]. Like [`source`](Self::source),
+    /// this ignores the [`table_path`](Self::table_path).
+    pub fn statmount(self) -> MountWatcherBuilder<M, WithSource>
+    where
+        StatmountTable<M>: MountTableSource<M>,
+    {
+        match StatmountTable::new() {
+            Ok(table) => self.with_source(Some(Box::new(table))),
+            Err(e) => {
+                log::debug!("listmount/statmount unavailable, using the mount table: {e}");
+                self.with_source(None)
+            }
+        }
+    }
+
+    /// Gets the attached and detached mounts from `fanotify`, instead of reading the
+    /// whole mount table each time a mount is attached or detached, if the kernel supports it
+    /// and if we have the `CAP_SYS_ADMIN` capability. Otherwise, the mount table is used.
+    ///
+    /// The events are built from the notifications, without comparing the whole table:
+    /// the remounts are not reported, see [`FanotifyTable`].
+    /// Like [`source`](Self::source), this ignores the [`table_path`](Self::table_path).
+    pub fn fanotify(self) -> MountWatcherBuilder<M, WithSource>
+    where
+        FanotifyTable<M>: MountTableSource<M>,
+    {
+        match FanotifyTable::new() {
+            Ok(table) => self.with_source(Some(Box::new(table))),
+            Err(e) => {
+                log::debug!("fanotify unavailable, using the mount table: {e}");
+                self.with_source(None)
+            }
+        }
+    }
+
+    /// Replaces the source, `None` meaning the mount table.
+    fn with_source(
+        self,
+        source: Option<Box<dyn MountTableSource<M>>>,
+    ) -> MountWatcherBuilder<M, WithSource> {
+        MountWatcherBuilder {
+            table: self.table,
+            thread_name: self.thread_name,
+            stack_size: self.stack_size,
+            initial_event: self.initial_event,
+            coalesce: self.coalesce,
+            filter: self.filter,
+            backing_path: self.backing_path,
+            error_policy: self.error_policy,
+            error_hook: self.error_hook,
+            catch_panics: self.catch_panics,
+            source,
+            fstab: self.fstab,
+            entry: PhantomData,
+            state: PhantomData,
+        }
+    }
+
+    /// Sets the name of the background thread.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.thread_name = Some(name.into());
+        self
+    }
+
+    /// Sets the stack size of the background thread, in bytes.
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// Chooses whether to call the callback with the initial event, which contains
+    /// the mounts that exist when the watcher starts. Defaults to `true`.
+    ///
+    /// When disabled, the callback only gets the subsequent changes.
+    pub fn initial_event(mut self, enabled: bool) -> Self {
+        self.initial_event = enabled;
+        self
+    }
+
+    /// Coalesces every event with the given delay.
+    ///
+    /// This is equivalent to wrapping the callback with [`callback::coalesce`](crate::callback::coalesce).
+    pub fn coalesce(mut self, delay: Duration, initial_event: CoalesceInitial) -> Self {
+        self.coalesce = Some((delay, initial_event));
+        self
+    }
+
+    /// Only reports the mounts that match the `filter`.
+    ///
+    /// The changes that only involve filtered-out mounts are ignored: they don't trigger
+    /// the callback, and they don't start any coalescing.
+    pub fn filter(mut self, filter: MountFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Only watches the mount that contains `path`, see [`find_mount_for_path`].
+    ///
+    /// An event is sent when this mount changes: when another filesystem is mounted
+    /// on `path` or on one of its parents, when the mount is unmounted, or when its options
+    /// are modified. The snapshot only contains this mount.
+    ///
+    /// When another filesystem is mounted over `path`, the event reports it in
+    /// [`changed`](MountEvent::changed), with the previous mount as `old`, because the
+    /// previous mount is still mounted. When the mount is really unmounted, it is reported in
+    /// [`unmounted`](MountEvent::unmounted), and the mount that now contains `path` in
+    /// [`mounted`](MountEvent::mounted).
+    ///
+    /// The path is canonicalized when the watcher is built, hence it must exist.
+    /// It is resolved by the current process, even if another [`pid`](Self::pid) is watched.
+    /// The mount is found with the mountinfo table, like [`find_mount_for_path`] does, to take
+    /// the overmounted filesystems into account. With a [`source`](Self::source),
+    /// a [`table_path`](Self::table_path) or a [`mount_namespace`](Self::mount_namespace),
+    /// the mountinfo table is unknown and [`MountEntry::find_for_path`] is used instead,
+    /// unless the entries come from [`mountinfo`](MountWatcherBuilder::mountinfo).
+    ///
+    /// [`find_mount_for_path`]: crate::mount::find_mount_for_path
+    pub fn backing_mount_of(mut self, path: impl Into<PathBuf>) -> Self {
+        self.backing_path = Some(path.into());
+        self
+    }
+
+    /// Chooses what to do when the mount table cannot be read, or when the callback
+    /// returns an error. Defaults to [`ErrorPolicy::Stop`].
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Calls `hook` on the background thread each time the mount table cannot be read
+    /// or the callback fails,
+    /// before applying the [`error_policy`](Self::error_policy).
+    pub fn on_error(mut self, hook: impl FnMut(&WatchError) + Send + 'static) -> Self {
+        self.error_hook = Some(ErrorHook(Box::new(hook)));
+        self
+    }
+
+    /// Also watches `/etc/fstab`, and reports its changes in [`MountEvent::fstab`].
+    ///
+    /// The file is read again shortly after each modification, with the mount table, and the
+    /// event contains the entries that have been added, removed or modified. Like the mounts,
+    /// the changes of fstab are coalesced when the callback returns
+    /// [`WatchControl::Coalesce`].
+    ///
+    /// If the file cannot be parsed, for instance because of a typo, a warning is logged and
+    /// the next valid version is reported.
+    pub fn watch_fstab(self) -> Self {
+        self.watch_fstab_file(FSTAB_PATH)
+    }
+
+    /// Like [`watch_fstab`](Self::watch_fstab), with another fstab file.
+    pub fn watch_fstab_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.fstab = Some(path.into());
+        self
+    }
+
+    /// Chooses whether to catch the panics of the callback. Defaults to `false`.
+    ///
+    /// When enabled, a panic is reported to the [`on_error`](Self::on_error) hook and
+    /// handled according to the [`error_policy`](Self::error_policy), like an error
+    /// returned by the callback. Otherwise, a panic kills the background thread.
+    ///
+    /// The callback may be left in an inconsistent state by the panic,
+    /// see [`std::panic::catch_unwind`].
+    pub fn catch_panics(mut self, enabled: bool) -> Self {
+        self.catch_panics = enabled;
+        self
+    }
+
+    /// Starts watching the mount table, and executes the `callback` when it changes.
+    pub fn build(
+        self,
+        callback: impl FnMut(MountEvent<M>) -> WatchControl + Send + 'static,
+    ) -> Result<MountWatcher<M>, SetupError> {
+        let mut callback = callback;
+        self.try_build(move |event| Ok::<_, Infallible>(callback(event)))
+    }
+
+    /// Starts watching the mount table, and executes the fallible `callback` when it changes.
+    ///
+    /// See [`MountWatcher::try_new`].
+    pub fn try_build<E: Into<CallbackError>>(
+        self,
+        callback: impl FnMut(MountEvent<M>) -> Result<WatchControl, E> + Send + 'static,
+    ) -> Result<MountWatcher<M>, SetupError> {
+        let mut callback = callback;
+        let callback = move |event| callback(event).map_err(Into::into);
+        match self.coalesce {
+            Some((delay, initial)) => watch_mounts(self, try_coalesce(delay, initial, callback)),
+            None => watch_mounts(self, callback),
+        }
+        .map_err(SetupError)
+    }
+
+    /// Starts watching the mount table, and sends the events to a channel.
+    ///
+    /// See [`MountWatcher::channel`].
+    pub fn build_channel(self) -> Result<MountEventReceiver<M>, SetupError> {
+        MountEventReceiver::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        fs::File,
+        io,
+        process::Command,
+        sync::mpsc::{self, RecvTimeoutError},
+        time::Duration,
+    };
+
+    use crate::{
+        callback::CoalesceInitial,
+        fanotify::FanotifyTable,
+        filter::MountFilter,
+        mount::{find_mount_for_path, list_current_mounts, LinuxMount},
+        table::FakeMountTable,
+        test_util::{in_mount_namespace, sys_mount, sys_umount},
+        watch::{ErrorPolicy, JoinError},
+        MountWatcher, WatchControl,
+    };
+
+    #[test]
+    fn no_initial_event() {
+        let events = MountWatcher::builder()
+            .initial_event(false)
+            .build_channel()
+            .unwrap();
+        assert!(matches!(
+            events.recv_timeout(Duration::from_millis(200)),
+            Err(RecvTimeoutError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn filtered_initial_event() {
+        let events = MountWatcher::builder()
+            .filter(MountFilter::new().fs_type("nonexistent-fs"))
+            .thread_name("test-watcher")
+            .build_channel()
+            .unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(event.initial);
+        assert!(event.is_empty());
+    }
+
+    #[test]
+    fn statmount_initial_event() {
+        // works with or without kernel support, thanks to the fallback
+        let events = MountWatcher::builder().statmount().build_channel().unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(event.initial);
+        let expected: HashSet<_> = list_current_mounts().unwrap().into_iter().collect();
+        let actual: HashSet<_> = event.mounted.into_iter().collect();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn fanotify_events() {
+        in_mount_namespace(|| {
+            if !FanotifyTable::<LinuxMount>::is_supported() {
+                eprintln!("fanotify mount events not supported, skipping the test");
+                return;
+            }
+            let dir = std::env::temp_dir().join(format!(
+                "mount-watcher-fanotify-events-{}",
+                std::process::id()
+            ));
+            let (a, b) = (dir.join("a"), dir.join("b"));
+            std::fs::create_dir_all(&a).unwrap();
+            std::fs::create_dir_all(&b).unwrap();
+
+            let events = MountWatcher::builder()
+                .filter(MountFilter::new().mount_point_prefix(&dir))
+                .coalesce(Duration::from_millis(200), CoalesceInitial::PassImmediately)
+                .fanotify()
+                .build_channel()
+                .unwrap();
+            assert!(events.recv_timeout(Duration::from_secs(5)).unwrap().initial);
+
+            // a is mounted and unmounted while coalescing, it is not reported
+            sys_mount(Some("none"), &a, Some("tmpfs"), 0);
+            sys_mount(Some("none"), &b, Some("tmpfs"), 0);
+            sys_umount(&a);
+            let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert!(event.coalesced);
+            let mount_points: Vec<_> = event.mounted.iter().map(|m| &m.mount_point).collect();
+            assert_eq!(mount_points, [&b]);
+            assert!(event.unmounted.is_empty() && event.changed.is_empty());
+            let mounted = event.mounted[0].clone();
+
+            // the remount is not notified
+            sys_mount(None, &b, None, libc::MS_REMOUNT | libc::MS_RDONLY);
+            assert!(events.recv_timeout(Duration::from_millis(500)).is_err());
+
+            sys_umount(&b);
+            let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert!(event.mounted.is_empty() && event.changed.is_empty());
+            assert_eq!(event.unmounted, [mounted]);
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+
+    #[test]
+    fn backing_mount_initial_event() {
+        let events = MountWatcher::builder()
+            .backing_mount_of("/proc/self")
+            .build_channel()
+            .unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(event.initial);
+        assert_eq!(
+            event.mounted,
+            vec![find_mount_for_path("/proc/self").unwrap().unwrap()]
+        );
+
+        assert!(MountWatcher::builder()
+            .backing_mount_of("/does/not/exist")
+            .build_channel()
+            .is_err());
+    }
+
+    #[test]
+    fn mountinfo_table_path() {
+        // the path of a /proc/mounts table is not kept, its lines cannot be parsed as mountinfo
+        let events = MountWatcher::builder()
+            .table_path("/proc/self/mounts")
+            .mountinfo()
+            .build_channel()
+            .unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(event.initial);
+        assert!(!event.mounted.is_empty());
+    }
+
+    #[test]
+    fn mountinfo_source() {
+        let table = FakeMountTable::new().unwrap();
+        let events = MountWatcher::builder()
+            .mountinfo()
+            .source(table)
+            .build_channel()
+            .unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(event.initial);
+        assert!(event.is_empty());
+    }
+
+    #[test]
+    fn watch_fstab() {
+        let dir = std::env::temp_dir().join(format!("mount-watcher-fstab-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("fstab");
+        std::fs::write(&path, "/dev/sda1 / ext4 defaults 0 1\n").unwrap();
+
+        let events = MountWatcher::builder()
+            .initial_event(false)
+            .watch_fstab_file(&path)
+            .build_channel()
+            .unwrap();
+        let recv = |timeout| {
+            let event = events.recv_timeout(timeout).ok()?;
+            assert!(event.mounted.is_empty() && !event.coalesced);
+            event.fstab
+        };
+
+        // modified in place
+        std::fs::write(&path, "/dev/sda1 / ext4 ro 0 1\n").unwrap();
+        let event = recv(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.changed.len(), 1);
+        assert_eq!(event.changed[0].new.mount_options, ["ro"]);
+
+        // replaced, like editors do
+        let tmp = dir.join(".fstab.tmp");
+        std::fs::write(
+            &tmp,
+            "/dev/sda1 / ext4 ro 0 1\ntmpfs /tmp tmpfs size=1G 0 0\n",
+        )
+        .unwrap();
+        std::fs::rename(&tmp, &path).unwrap();
+        let event = recv(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.added.len(), 1);
+        assert_eq!(event.added[0].fs_type, "tmpfs");
+        assert_eq!(event.entries.len(), 2);
+
+        // other files are ignored
+        std::fs::write(dir.join("other"), "x").unwrap();
+        assert!(recv(Duration::from_millis(300)).is_none());
+
+        events.stop().unwrap();
+        events.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn watch_fstab_symlink() {
+        let dir =
+            std::env::temp_dir().join(format!("mount-watcher-fstab-link-{}", std::process::id()));
+        let real = dir.join("real");
+        std::fs::create_dir_all(&real).unwrap();
+        std::fs::write(real.join("a"), "/dev/sda1 / ext4 defaults 0 1\n").unwrap();
+        std::fs::write(real.join("b"), "/dev/sdb1 / ext4 defaults 0 1\n").unwrap();
+        let path = dir.join("fstab");
+        std::os::unix::fs::symlink("real/a", &path).unwrap();
+
+        let events = MountWatcher::builder()
+            .initial_event(false)
+            .watch_fstab_file(&path)
+            .build_channel()
+            .unwrap();
+        let recv = |timeout| {
+            let event = events.recv_timeout(timeout).ok()?;
+            assert!(event.mounted.is_empty() && !event.coalesced);
+            event.fstab
+        };
+
+        // the target is modified
+        std::fs::write(real.join("a"), "/dev/sda1 / ext4 ro 0 1\n").unwrap();
+        let event = recv(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.changed.len(), 1);
+
+        // the link is replaced
+        std::os::unix::fs::symlink("real/b", dir.join("fstab.new")).unwrap();
+        std::fs::rename(dir.join("fstab.new"), &path).unwrap();
+        let event = recv(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.entries[0].spec, "/dev/sdb1");
+
+        // the old target is ignored, the new one is watched
+        std::fs::write(real.join("a"), "/dev/sda1 / ext4 rw 0 1\n").unwrap();
+        assert!(recv(Duration::from_millis(300)).is_none());
+        std::fs::write(real.join("b"), "/dev/sdb1 / ext4 ro 0 1\n").unwrap();
+        let event = recv(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.changed[0].new.mount_options, ["ro"]);
+
+        events.stop().unwrap();
+        events.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn watch_pid() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = child.id();
+        let events = MountWatcher::builder().pid(pid).build_channel().unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(event.initial);
+        // the child is in our mount namespace
+        let expected: HashSet<_> = list_current_mounts().unwrap().into_iter().collect();
+        let actual: HashSet<_> = event.mounted.into_iter().collect();
+        assert_eq!(expected, actual);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        match events.join() {
+            Err(JoinError::Watch(e)) => assert_eq!(e.exited_pid(), Some(pid)),
+            res => panic!("unexpected result {res:?}"),
+        }
+    }
+
+    #[test]
+    fn watch_namespace() {
+        let ns = File::open("/proc/self/ns/mnt").unwrap();
+        let Ok(events) = MountWatcher::builder().mount_namespace(ns).build_channel() else {
+            eprintln!("cannot enter the mount namespace, skipping the test");
+            return;
+        };
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        let expected: HashSet<_> = list_current_mounts().unwrap().into_iter().collect();
+        let actual: HashSet<_> = event.mounted.into_iter().collect();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn fanotify_initial_event() {
+        let events = MountWatcher::builder().fanotify().build_channel().unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(event.initial);
+        let expected: HashSet<_> = list_current_mounts().unwrap().into_iter().collect();
+        let actual: HashSet<_> = event.mounted.into_iter().collect();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn callback_error() {
+        let watch =
+            MountWatcher::try_new(|_| Err(io::Error::new(io::ErrorKind::Other, "oops"))).unwrap();
+        match watch.join() {
+            Err(JoinError::Watch(e)) => {
+                let e = e.as_callback_error().unwrap();
+                assert_eq!(e.downcast_ref::<io::Error>().unwrap().to_string(), "oops");
+            }
+            res => panic!("unexpected result {res:?}"),
+        }
+    }
+
+    #[test]
+    fn callback_error_retry() {
+        let (tx, rx) = mpsc::channel();
+        let watch = MountWatcher::builder()
+            .error_policy(ErrorPolicy::Retry {
+                initial_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(10),
+            })
+            .try_build(move |event| {
+                tx.send(event.initial).unwrap();
+                Err("oops")
+            })
+            .unwrap();
+        // the initial event is sent again and again
+        for _ in 0..3 {
+            assert!(rx.recv_timeout(Duration::from_secs(5)).unwrap());
+        }
+        watch.stop().unwrap();
+        watch.join().unwrap();
+    }
+
+    #[test]
+    fn callback_panic() {
+        let watch = MountWatcher::new(|_| panic!("oops")).unwrap();
+        let e = watch.join().unwrap_err();
+        assert_eq!(e.panic_message(), Some("oops"));
+        assert_eq!(e.to_string(), "MountWatcher thread has panicked: oops");
+        assert!(matches!(e, JoinError::Panicked(payload) if payload.is::<&str>()));
+    }
+
+    #[test]
+    fn callback_panic_caught() {
+        let (tx, rx) = mpsc::channel();
+        let watch = MountWatcher::builder()
+            .catch_panics(true)
+            .error_policy(ErrorPolicy::Skip)
+            .on_error(move |e| tx.send(e.as_panic_message().map(String::from)).unwrap())
+            .build(|event| {
+                if event.initial {
+                    panic!("oops {}", 1);
+                }
+                WatchControl::Continue
+            })
+            .unwrap();
+        let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(msg.as_deref(), Some("oops 1"));
+        // the watcher is still alive
+        watch.stop().unwrap();
+        watch.join().unwrap();
+    }
+}
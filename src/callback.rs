@@ -6,6 +6,7 @@ use crate::{MountEvent, WatchControl};
 
 /// How to handle the initial event, which contains the list of mount points that
 /// have been detected when the watcher has started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CoalesceInitial {
     /// Coalesce the initial event like any other event.
     Coalesce,
@@ -41,20 +42,40 @@ pub enum CoalesceInitial {
 ///     )
 /// );
 /// ```
-pub fn coalesce<F: FnMut(MountEvent) -> WatchControl + Send + 'static>(
+pub fn coalesce<M, F: FnMut(MountEvent<M>) -> WatchControl + Send + 'static>(
     delay: Duration,
     initial_event: CoalesceInitial,
     mut f: F,
-) -> impl FnMut(MountEvent) -> WatchControl + Send + 'static {
+) -> impl FnMut(MountEvent<M>) -> WatchControl + Send + 'static {
     move |event| {
-        let coalesce = match initial_event {
-            CoalesceInitial::Coalesce => !event.coalesced,
-            CoalesceInitial::PassImmediately => !(event.coalesced || event.initial),
-        };
-        if coalesce {
+        if must_coalesce(initial_event, &event) {
             WatchControl::Coalesce { delay }
         } else {
             f(event)
         }
     }
 }
+
+/// Like [`coalesce`], for a fallible callback.
+///
+/// See [`MountWatcher::try_new`](crate::MountWatcher::try_new).
+pub fn try_coalesce<M, E, F: FnMut(MountEvent<M>) -> Result<WatchControl, E> + Send + 'static>(
+    delay: Duration,
+    initial_event: CoalesceInitial,
+    mut f: F,
+) -> impl FnMut(MountEvent<M>) -> Result<WatchControl, E> + Send + 'static {
+    move |event| {
+        if must_coalesce(initial_event, &event) {
+            Ok(WatchControl::Coalesce { delay })
+        } else {
+            f(event)
+        }
+    }
+}
+
+fn must_coalesce<M>(initial_event: CoalesceInitial, event: &MountEvent<M>) -> bool {
+    match initial_event {
+        CoalesceInitial::Coalesce => !event.coalesced,
+        CoalesceInitial::PassImmediately => !(event.coalesced || event.initial),
+    }
+}
@@ -0,0 +1,178 @@
+//! Blocking interface, based on a channel.
+//!
+//! Instead of moving all your state into a callback, you can receive the events
+//! on the current thread with a [`MountEventReceiver`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use mount_watcher::MountWatcher;
+//!
+//! let events = MountWatcher::channel().unwrap();
+//! for event in events.iter() {
+//!     println!("new mounts: {:?}", event.mounted);
+//!     println!("removed mounts: {:?}", event.unmounted);
+//! }
+//! ```
+
+use std::{
+    sync::mpsc::{self, Receiver, RecvError, RecvTimeoutError, TryRecvError},
+    time::Duration,
+};
+
+use crate::{
+    builder::MountWatcherBuilder,
+    mount::{LinuxMount, MountEntry},
+    mountinfo::MountInfo,
+    watch::{JoinError, MountSnapshot, SetupError, StopError},
+    MountEvent, MountWatcher, WatchControl,
+};
+
+/// Receives the events of a [`MountWatcher`] through a channel.
+///
+/// The background thread of the watcher sends the events to the channel, and you
+/// get them with [`recv`](Self::recv), [`recv_timeout`](Self::recv_timeout),
+/// [`try_recv`](Self::try_recv) or by iterating over the receiver.
+///
+/// # Stopping
+///
+/// When the `MountEventReceiver` is dropped, the watcher is stopped.
+/// You can also call [`stop`](Self::stop): the events that have already been sent
+/// can still be received, and then the iteration ends.
+pub struct MountEventReceiver<M = LinuxMount> {
+    watcher: MountWatcher<M>,
+    rx: Receiver<MountEvent<M>>,
+}
+
+impl MountWatcher {
+    /// Watches the list of mounted filesystems and sends the events to a channel.
+    ///
+    /// Unlike [`new`](Self::new), this allows to handle the events on the current thread.
+    /// To configure the watcher, use [`MountWatcherBuilder::build_channel`].
+    pub fn channel() -> Result<MountEventReceiver, SetupError> {
+        MountWatcherBuilder::new().build_channel()
+    }
+
+    /// Watches `/proc/self/mountinfo` and sends the events to a channel.
+    ///
+    /// See [`channel`](Self::channel) and [`new_mountinfo`](Self::new_mountinfo).
+    pub fn channel_mountinfo() -> Result<MountEventReceiver<MountInfo>, SetupError> {
+        MountWatcherBuilder::new().mountinfo().build_channel()
+    }
+}
+
+impl<M: MountEntry> MountEventReceiver<M> {
+    pub(crate) fn new<S>(config: MountWatcherBuilder<M, S>) -> Result<Self, SetupError> {
+        let (tx, rx) = mpsc::channel();
+        let watcher = config.build(move |event| match tx.send(event) {
+            Ok(()) => WatchControl::Continue,
+            Err(_) => WatchControl::Stop, // the receiver has been dropped
+        })?;
+        Ok(Self { watcher, rx })
+    }
+
+    /// Waits for the next event.
+    ///
+    /// Returns an error if the watcher has stopped and all the events have been received.
+    pub fn recv(&self) -> Result<MountEvent<M>, RecvError> {
+        self.rx.recv()
+    }
+
+    /// Waits for the next event, for at most `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<MountEvent<M>, RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+
+    /// Returns the next event if there is one, without blocking.
+    pub fn try_recv(&self) -> Result<MountEvent<M>, TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    /// Returns an iterator that blocks while waiting for the events.
+    ///
+    /// The iteration ends when the watcher stops.
+    pub fn iter(&self) -> impl Iterator<Item = MountEvent<M>> + '_ {
+        self.rx.iter()
+    }
+
+    /// Returns an iterator over the events that have already been sent, without blocking.
+    pub fn try_iter(&self) -> impl Iterator<Item = MountEvent<M>> + '_ {
+        self.rx.try_iter()
+    }
+
+    /// Requests the background thread to terminate.
+    ///
+    /// See [`MountWatcher::stop`].
+    pub fn stop(&self) -> Result<(), StopError> {
+        self.watcher.stop()
+    }
+
+    /// Returns the mounts that are known by the watcher.
+    ///
+    /// See [`MountWatcher::snapshot`].
+    pub fn snapshot(&self) -> MountSnapshot<M> {
+        self.watcher.snapshot()
+    }
+
+    /// Waits for the background thread to terminate, and returns its error, if any.
+    ///
+    /// The events that have not been received are dropped. See [`MountWatcher::join`].
+    pub fn join(self) -> Result<(), JoinError> {
+        self.watcher.join()
+    }
+}
+
+impl<M: MountEntry> Iterator for MountEventReceiver<M> {
+    type Item = MountEvent<M>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rx.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::mpsc::TryRecvError, time::Duration};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{mount::list_current_mounts, MountWatcher};
+
+    #[test]
+    fn initial_event() {
+        let events = MountWatcher::channel().unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(event.initial);
+        assert!(event.unmounted.is_empty());
+
+        let expected: HashSet<_> = list_current_mounts().unwrap().into_iter().collect();
+        let actual: HashSet<_> = event.mounted.into_iter().collect();
+        assert_eq!(expected, actual);
+
+        assert!(matches!(events.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[test]
+    fn stop_ends_iteration() {
+        let events = MountWatcher::channel().unwrap();
+        events.stop().unwrap();
+        // the initial event may or may not have been sent before the stop
+        assert!(events.iter().count() <= 1);
+    }
+
+    #[test]
+    fn snapshot() {
+        let events = MountWatcher::channel().unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.generation, 1);
+
+        // the snapshot is updated right after the event has been sent
+        let mut snapshot = events.snapshot();
+        while snapshot.generation() < event.generation {
+            std::thread::sleep(Duration::from_millis(1));
+            snapshot = events.snapshot();
+        }
+        let expected: HashSet<_> = event.mounted.into_iter().collect();
+        assert_eq!(snapshot.mounts(), &expected);
+    }
+}
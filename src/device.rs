@@ -0,0 +1,429 @@
+//! Resolve the spec of a mount to a block device.
+//!
+//! The spec of a mount, or of an fstab entry, can designate a block device in several ways:
+//! `/dev/nvme0n1p1`, `/dev/mapper/root`, `UUID=...`, `LABEL=...` and so on, see [`FsSpec`].
+//! [`DeviceResolver`] finds the device with the symlinks of `/dev/disk/by-*`, and gets its
+//! details from sysfs.
+//!
+//! ```no_run
+//! use mount_watcher::{device::DeviceResolver, mountinfo::list_current_mountinfo};
+//!
+//! let resolver = DeviceResolver::new();
+//! for mount in list_current_mountinfo().unwrap() {
+//!     if let Some(device) = resolver.by_dev_number(mount.major, mount.minor).unwrap() {
+//!         println!("disk {:?} is mounted at {:?}", device.serial, mount.mount_point);
+//!     }
+//! }
+//! ```
+
+use std::{
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::fstab::FsSpec;
+
+/// Maximum number of symlinks to follow, like the kernel.
+const MAX_SYMLINKS: usize = 40;
+
+/// Finds block devices in `/dev` and `/sys`.
+///
+/// The lookups go through a root directory, `/` by default, so that they can be tested
+/// with a fake tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceResolver {
+    root: PathBuf,
+}
+
+/// A block device, see [`DeviceResolver`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BlockDevice {
+    /// Path of the device, such as `/dev/sda1` (without the root of the resolver).
+    pub path: PathBuf,
+    /// Major number of the device.
+    pub major: u32,
+    /// Minor number of the device.
+    pub minor: u32,
+    /// UUID of the filesystem, from `/dev/disk/by-uuid`.
+    pub uuid: Option<String>,
+    /// Label of the filesystem, from `/dev/disk/by-label`.
+    pub label: Option<String>,
+    /// UUID of the partition, from `/dev/disk/by-partuuid`.
+    pub part_uuid: Option<String>,
+    /// Label of the partition, from `/dev/disk/by-partlabel`.
+    pub part_label: Option<String>,
+    /// Serial number of the disk, from sysfs or from the udev database.
+    ///
+    /// For a partition, this is the serial number of the disk that contains it.
+    pub serial: Option<String>,
+}
+
+impl BlockDevice {
+    /// Returns `true` if `spec` designates this device.
+    ///
+    /// A path matches if it is the path of the device: use [`DeviceResolver::resolve`] to
+    /// follow the symlinks, such as `/dev/mapper/root`.
+    pub fn matches(&self, spec: &FsSpec) -> bool {
+        let eq = |value: &Option<String>, expected: &str| value.as_deref() == Some(expected);
+        match spec {
+            FsSpec::Uuid(uuid) => eq(&self.uuid, uuid),
+            FsSpec::Label(label) => eq(&self.label, label),
+            FsSpec::PartUuid(uuid) => eq(&self.part_uuid, uuid),
+            FsSpec::PartLabel(label) => eq(&self.part_label, label),
+            FsSpec::Path(path) => &self.path == path,
+            FsSpec::Other(_) => false,
+        }
+    }
+}
+
+impl Default for DeviceResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeviceResolver {
+    /// Looks up the devices of this system.
+    pub fn new() -> Self {
+        Self::with_root("/")
+    }
+
+    /// Looks up the devices in `root`, which must contain `dev` and `sys` directories.
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Finds the device designated by `spec`.
+    ///
+    /// Returns `None` if there is no such device, or if the spec is not a device,
+    /// like `tmpfs` or `server:/export`.
+    pub fn resolve(&self, spec: &FsSpec) -> io::Result<Option<BlockDevice>> {
+        let path = match spec {
+            FsSpec::Uuid(uuid) => disk_link("by-uuid", uuid),
+            FsSpec::Label(label) => disk_link("by-label", label),
+            FsSpec::PartUuid(uuid) => disk_link("by-partuuid", uuid),
+            FsSpec::PartLabel(label) => disk_link("by-partlabel", label),
+            FsSpec::Path(path) => path.clone(),
+            FsSpec::Other(_) => return Ok(None),
+        };
+        match self.follow_links(&path)? {
+            Some(path) => self.by_path(&path),
+            None => Ok(None),
+        }
+    }
+
+    /// Finds the device with the given major and minor numbers, for instance those of
+    /// [`MountInfo`](crate::mountinfo::MountInfo).
+    ///
+    /// Returns `None` if there is no such block device, like for a `tmpfs`.
+    pub fn by_dev_number(&self, major: u32, minor: u32) -> io::Result<Option<BlockDevice>> {
+        let sys_dir = self.path(format!("/sys/dev/block/{major}:{minor}"));
+        if !sys_dir.exists() {
+            return Ok(None);
+        }
+        let uevent = read_optional(&sys_dir.join("uevent"))?.unwrap_or_default();
+        let name = uevent
+            .lines()
+            .find_map(|l| l.strip_prefix("DEVNAME="))
+            .map(str::to_owned);
+        let name = match name {
+            Some(name) => name,
+            // the sysfs directory is named after the device, with `!` instead of `/`
+            None => match fs::canonicalize(&sys_dir)?.file_name() {
+                Some(name) => name.to_string_lossy().replace('!', "/"),
+                None => return Ok(None),
+            },
+        };
+        self.by_path(&Path::new("/dev").join(name))
+    }
+
+    /// Gets the details of the device at `path`, which must not be a symlink.
+    fn by_path(&self, path: &Path) -> io::Result<Option<BlockDevice>> {
+        let Ok(name) = path.strip_prefix("/dev") else {
+            return Ok(None);
+        };
+        let sys_name = name.to_string_lossy().replace('/', "!");
+        let sys_dir = self.path("/sys/class/block").join(&sys_name);
+        let Some(dev) = read_optional(&sys_dir.join("dev"))? else {
+            return Ok(None);
+        };
+        let Some((major, minor)) = dev
+            .trim()
+            .split_once(':')
+            .and_then(|(a, b)| Some((a.parse().ok()?, b.parse().ok()?)))
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid device number in {sys_dir:?}: {dev}"),
+            ));
+        };
+
+        Ok(Some(BlockDevice {
+            path: path.to_owned(),
+            major,
+            minor,
+            uuid: self.find_link("by-uuid", path)?,
+            label: self.find_link("by-label", path)?,
+            part_uuid: self.find_link("by-partuuid", path)?,
+            part_label: self.find_link("by-partlabel", path)?,
+            serial: self.serial(&sys_dir, major, minor)?,
+        }))
+    }
+
+    /// Returns the name of the link of `/dev/disk/<dir>` that points to `device`.
+    fn find_link(&self, dir: &str, device: &Path) -> io::Result<Option<String>> {
+        let entries = match fs::read_dir(self.path("/dev/disk").join(dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let name = entry?.file_name();
+            let link = Path::new("/dev/disk").join(dir).join(&name);
+            if self.follow_links(&link)?.as_deref() == Some(device) {
+                return Ok(Some(decode_udev(name.as_bytes())));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the serial number of a disk, or of the disk that contains a partition.
+    fn serial(&self, sys_dir: &Path, major: u32, minor: u32) -> io::Result<Option<String>> {
+        let disk_dir = if sys_dir.join("partition").exists() {
+            // class/block/sda1 is a link to devices/.../sda/sda1
+            match fs::canonicalize(sys_dir)?.parent() {
+                Some(parent) => parent.to_owned(),
+                None => return Ok(None),
+            }
+        } else {
+            sys_dir.to_owned()
+        };
+        for file in ["device/serial", "serial"] {
+            if let Some(serial) = read_optional(&disk_dir.join(file))? {
+                let serial = serial.trim();
+                if !serial.is_empty() {
+                    return Ok(Some(serial.to_owned()));
+                }
+            }
+        }
+        // SATA disks don't expose their serial number in sysfs, but udev knows it
+        let udev = self.path(format!("/run/udev/data/b{major}:{minor}"));
+        let serial = read_optional(&udev)?.and_then(|data| {
+            data.lines()
+                .find_map(|l| l.strip_prefix("E:ID_SERIAL_SHORT="))
+                .map(str::to_owned)
+        });
+        Ok(serial)
+    }
+
+    /// Follows the symlinks of `path`, inside the root.
+    ///
+    /// Returns `None` if the file does not exist.
+    fn follow_links(&self, path: &Path) -> io::Result<Option<PathBuf>> {
+        let mut path = normalize(path);
+        for _ in 0..MAX_SYMLINKS {
+            match fs::read_link(self.path(&path)) {
+                Ok(target) => {
+                    let parent = path.parent().unwrap_or(Path::new("/"));
+                    path = normalize(&parent.join(target));
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                // not a symlink
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => return Ok(Some(path)),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("too many levels of symbolic links: {path:?}"),
+        ))
+    }
+
+    /// Converts an absolute path of the system to a path in the root.
+    fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = path.as_ref();
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+}
+
+/// Path of the link that udev creates in `/dev/disk/<dir>` for `value`.
+fn disk_link(dir: &str, value: &str) -> PathBuf {
+    Path::new("/dev/disk").join(dir).join(encode_udev(value))
+}
+
+/// Escapes a value like udev does in the names of the links, e.g. `My Disk` becomes `My\x20Disk`.
+fn encode_udev(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        // the non-ASCII characters are kept
+        if c.is_ascii_alphanumeric() || "#+-.:=@_".contains(c) || !c.is_ascii() {
+            encoded.push(c);
+        } else {
+            encoded.push_str(&format!("\\x{:02x}", c as u32));
+        }
+    }
+    encoded
+}
+
+/// Decodes the `\xHH` sequences of a link name.
+fn decode_udev(name: &[u8]) -> String {
+    let mut decoded = Vec::with_capacity(name.len());
+    let mut i = 0;
+    while i < name.len() {
+        let hex = name
+            .get(i + 2..i + 4)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(b) if name[i..].starts_with(b"\\x") => {
+                decoded.push(b);
+                i += 4;
+            }
+            _ => {
+                decoded.push(name[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Removes the `.` and `..` components of an absolute path, without following the links.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => normalized.push(c),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => (),
+        }
+    }
+    normalized
+}
+
+/// Reads a text file, or returns `None` if it does not exist.
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(String::from_utf8_lossy(&content).into_owned())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        os::unix::fs::symlink,
+        path::{Path, PathBuf},
+    };
+
+    use pretty_assertions::assert_eq;
+
+    use super::{decode_udev, encode_udev, BlockDevice, DeviceResolver};
+    use crate::fstab::FsSpec;
+
+    /// Creates a fake tree with a disk `sda` (serial `S123`) and a partition `sda1`,
+    /// and a device-mapper device `dm-0` with a label.
+    fn fake_root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("mount-watcher-dev-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let mkdir = |p: &str| fs::create_dir_all(root.join(p)).unwrap();
+        let write = |p: &str, content: &str| fs::write(root.join(p), content).unwrap();
+        let link = |target: &str, p: &str| symlink(target, root.join(p)).unwrap();
+
+        let sda = "devices/pci0000:00/0000:00:01.0/host0/block/sda";
+        mkdir(&format!("sys/{sda}/sda1"));
+        mkdir(&format!("sys/{sda}/device"));
+        write(&format!("sys/{sda}/dev"), "8:0\n");
+        write(&format!("sys/{sda}/device/serial"), "S123\n");
+        write(
+            &format!("sys/{sda}/uevent"),
+            "MAJOR=8\nMINOR=0\nDEVNAME=sda\n",
+        );
+        write(&format!("sys/{sda}/sda1/dev"), "8:1\n");
+        write(&format!("sys/{sda}/sda1/partition"), "1\n");
+        write(
+            &format!("sys/{sda}/sda1/uevent"),
+            "MAJOR=8\nMINOR=1\nDEVNAME=sda1\n",
+        );
+        mkdir("sys/devices/virtual/block/dm-0");
+        write("sys/devices/virtual/block/dm-0/dev", "253:0\n");
+
+        mkdir("sys/class/block");
+        mkdir("sys/dev/block");
+        link(&format!("../../{sda}"), "sys/class/block/sda");
+        link(&format!("../../{sda}/sda1"), "sys/class/block/sda1");
+        link("../../devices/virtual/block/dm-0", "sys/class/block/dm-0");
+        link(&format!("../../{sda}"), "sys/dev/block/8:0");
+        link(&format!("../../{sda}/sda1"), "sys/dev/block/8:1");
+        link("../../devices/virtual/block/dm-0", "sys/dev/block/253:0");
+
+        mkdir("dev/mapper");
+        mkdir("dev/disk/by-uuid");
+        mkdir("dev/disk/by-label");
+        mkdir("dev/disk/by-partuuid");
+        write("dev/sda", "");
+        write("dev/sda1", "");
+        write("dev/dm-0", "");
+        link("../dm-0", "dev/mapper/root");
+        link("../../sda1", "dev/disk/by-uuid/0a3407de-014b-458b");
+        link("../../sda1", "dev/disk/by-partuuid/1234-01");
+        link("../../dm-0", "dev/disk/by-label/My\\x20Root");
+        root
+    }
+
+    #[test]
+    fn resolve() {
+        let root = fake_root();
+        let resolver = DeviceResolver::with_root(&root);
+        let sda1 = BlockDevice {
+            path: PathBuf::from("/dev/sda1"),
+            major: 8,
+            minor: 1,
+            uuid: Some("0a3407de-014b-458b".into()),
+            label: None,
+            part_uuid: Some("1234-01".into()),
+            part_label: None,
+            serial: Some("S123".into()),
+        };
+
+        let resolve = |spec: &str| resolver.resolve(&FsSpec::parse(spec.as_ref())).unwrap();
+        assert_eq!(resolve("/dev/sda1"), Some(sda1.clone()));
+        assert_eq!(resolve("UUID=0a3407de-014b-458b"), Some(sda1.clone()));
+        assert_eq!(resolve("PARTUUID=1234-01"), Some(sda1.clone()));
+        assert_eq!(resolve("UUID=ffff"), None);
+        assert_eq!(resolve("tmpfs"), None);
+
+        let root_fs = resolve("/dev/mapper/root").unwrap();
+        assert_eq!(root_fs.path, Path::new("/dev/dm-0"));
+        assert_eq!((root_fs.major, root_fs.minor), (253, 0));
+        assert_eq!(root_fs.label.as_deref(), Some("My Root"));
+        assert_eq!(root_fs.serial, None);
+        assert_eq!(resolve("LABEL=My Root"), Some(root_fs.clone()));
+
+        assert!(sda1.matches(&FsSpec::Uuid("0a3407de-014b-458b".into())));
+        assert!(!sda1.matches(&FsSpec::Label("My Root".into())));
+
+        // reverse lookup
+        assert_eq!(resolver.by_dev_number(8, 1).unwrap(), Some(sda1));
+        assert_eq!(resolver.by_dev_number(253, 0).unwrap(), Some(root_fs));
+        let sda = resolver.by_dev_number(8, 0).unwrap().unwrap();
+        assert_eq!(sda.path, Path::new("/dev/sda"));
+        assert_eq!(sda.uuid, None);
+        assert_eq!(resolver.by_dev_number(0, 22).unwrap(), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn udev_escaping() {
+        assert_eq!(encode_udev("My Disk/1"), "My\\x20Disk\\x2f1");
+        assert_eq!(encode_udev("données"), "données");
+        assert_eq!(decode_udev(b"My\\x20Disk\\x2f1"), "My Disk/1");
+        assert_eq!(decode_udev(b"bad\\xzz"), "bad\\xzz");
+    }
+}
@@ -0,0 +1,409 @@
+//! Mount table kept up to date with the mount notifications of `fanotify`.
+//!
+//! Since Linux 6.15, `fanotify` can report each mount that is attached to or detached from
+//! a mount namespace. [`FanotifyTable`] applies these notifications to its own copy of the
+//! mount table, so that the kernel's table is not read again when a mount is attached or
+//! detached: only the attached mounts are read, with `statmount`.
+//! The watcher builds its events from the notifications, without comparing the whole list
+//! with the previous one, see [`MountTableSource::read_changes`].
+//!
+//! This requires `CAP_SYS_ADMIN`. [`MountWatcherBuilder::fanotify`] uses it if possible,
+//! and falls back to the mount table file otherwise.
+//!
+//! [`MountWatcherBuilder::fanotify`]: crate::MountWatcherBuilder::fanotify
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Read},
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+};
+
+use mio::Interest;
+
+use crate::{
+    mount::{LinuxMount, MountEntry, ReadError},
+    mountinfo::MountInfo,
+    statmount::MountStats,
+    table::{MountTableSource, TableChanges},
+};
+
+const FAN_CLOEXEC: libc::c_uint = 0x1;
+const FAN_NONBLOCK: libc::c_uint = 0x2;
+const FAN_CLASS_NOTIF: libc::c_uint = 0x0;
+const FAN_REPORT_MNT: libc::c_uint = 0x4000;
+
+const FAN_MARK_ADD: libc::c_uint = 0x1;
+const FAN_MARK_MNTNS: libc::c_uint = 0x110;
+
+const FAN_Q_OVERFLOW: u64 = 0x4000;
+const FAN_MNT_ATTACH: u64 = 0x0100_0000;
+const FAN_MNT_DETACH: u64 = 0x0200_0000;
+
+const FAN_EVENT_INFO_TYPE_MNT: u8 = 7;
+const FANOTIFY_METADATA_VERSION: u8 = 3;
+
+/// Mount table of the current mount namespace, updated with `fanotify`.
+///
+/// The kernel only notifies the mounts that are attached or detached. The other changes,
+/// for instance a remount in read-only mode, are not reported: the mounts keep the options
+/// that they had when they were attached, until the whole table is read again after
+/// a queue overflow. Use the mount table file to detect them.
+#[derive(Debug)]
+pub struct FanotifyTable<M = LinuxMount> {
+    fd: File,
+    stats: MountStats,
+    /// The mounts by ID, in the order of their creation like in the other tables.
+    mounts: BTreeMap<u64, M>,
+    /// Reads the whole table on the next read: at the beginning, and after a queue overflow.
+    resync: bool,
+    buf: Vec<u8>,
+}
+
+impl<M: MountEntry> FanotifyTable<M> {
+    /// Starts listening to the mount notifications of the current mount namespace.
+    ///
+    /// Returns an error of kind [`Unsupported`](io::ErrorKind::Unsupported) if the kernel
+    /// is too old, or [`PermissionDenied`](io::ErrorKind::PermissionDenied) without `CAP_SYS_ADMIN`.
+    pub fn new() -> io::Result<Self> {
+        let unsupported = |e: io::Error| match e.raw_os_error() {
+            Some(libc::ENOSYS | libc::EINVAL) => io::Error::new(
+                io::ErrorKind::Unsupported,
+                "fanotify mount events not supported",
+            ),
+            _ => e,
+        };
+
+        // SAFETY: no pointer is involved
+        let fd = unsafe {
+            libc::fanotify_init(
+                FAN_CLASS_NOTIF | FAN_CLOEXEC | FAN_NONBLOCK | FAN_REPORT_MNT,
+                (libc::O_RDONLY | libc::O_CLOEXEC) as libc::c_uint,
+            )
+        };
+        if fd < 0 {
+            return Err(unsupported(io::Error::last_os_error()));
+        }
+        // SAFETY: the fd has just been created, and nothing else owns it
+        let fd = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        // The syscalls use the namespace of the thread, which is not always the one of the process.
+        let ns = File::open("/proc/thread-self/ns/mnt")?;
+        // SAFETY: the path can be null with FAN_MARK_MNTNS
+        let res = unsafe {
+            libc::fanotify_mark(
+                fd.as_raw_fd(),
+                FAN_MARK_ADD | FAN_MARK_MNTNS,
+                FAN_MNT_ATTACH | FAN_MNT_DETACH,
+                ns.as_raw_fd(),
+                std::ptr::null(),
+            )
+        };
+        if res < 0 {
+            return Err(unsupported(io::Error::last_os_error()));
+        }
+
+        Ok(Self {
+            fd,
+            stats: MountStats::new()?,
+            mounts: BTreeMap::new(),
+            resync: true,
+            buf: vec![0; 4096],
+        })
+    }
+
+    /// Returns `true` if the kernel supports the mount notifications and if we are
+    /// allowed to use them.
+    pub fn is_supported() -> bool {
+        Self::new().is_ok()
+    }
+
+    /// Reads the pending notifications.
+    fn read_notifications(&mut self) -> io::Result<Vec<Notification>> {
+        let mut notifications = Vec::new();
+        loop {
+            let n = match (&self.fd).read(&mut self.buf) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(notifications),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            parse_events(&self.buf[..n], &mut notifications)?;
+        }
+    }
+
+    /// Applies the notifications to our copy of the table, with `get` to read an attached mount,
+    /// and returns the mounts that have been attached and detached.
+    ///
+    /// Returns `None` if all the mounts have been read again with `all`, at the beginning
+    /// or after a queue overflow.
+    fn update(
+        &mut self,
+        get: impl Fn(&mut MountStats, u64) -> io::Result<Option<M>>,
+        all: impl Fn(&mut MountStats) -> io::Result<Vec<(u64, M)>>,
+    ) -> Result<Option<TableChanges<M>>, ReadError> {
+        let notifications = self.read_notifications()?;
+        if notifications.contains(&Notification::Overflow) {
+            log::debug!("the fanotify queue has overflowed, reading all the mounts");
+            self.resync = true;
+        }
+        if self.resync {
+            // The notifications that we have drained are included in the new table.
+            self.mounts = all(&mut self.stats)?.into_iter().collect();
+            self.resync = false;
+            return Ok(None);
+        }
+        let mut changes = TableChanges::default();
+        for notification in notifications {
+            match notification {
+                Notification::Attach(id) => {
+                    // None if it has already been detached, we'll ignore the detach too.
+                    if let Some(mount) = get(&mut self.stats, id)? {
+                        self.mounts.insert(id, mount.clone());
+                        changes.mounted.push(mount);
+                    }
+                }
+                Notification::Detach(id) => {
+                    if let Some(mount) = self.mounts.remove(&id) {
+                        changes.merge(TableChanges {
+                            mounted: Vec::new(),
+                            unmounted: vec![mount],
+                        });
+                    }
+                }
+                Notification::Overflow => (),
+            }
+        }
+        Ok(Some(changes))
+    }
+
+    /// Applies the notifications, and returns the whole table.
+    fn read_all(
+        &mut self,
+        get: impl Fn(&mut MountStats, u64) -> io::Result<Option<M>>,
+        all: impl Fn(&mut MountStats) -> io::Result<Vec<(u64, M)>>,
+    ) -> Result<Vec<M>, ReadError> {
+        self.update(get, all)?;
+        Ok(self.mounts.values().cloned().collect())
+    }
+}
+
+impl MountTableSource<LinuxMount> for FanotifyTable<LinuxMount> {
+    fn read(&mut self) -> Result<Vec<LinuxMount>, ReadError> {
+        self.read_all(MountStats::get, MountStats::all)
+    }
+
+    fn readiness_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+
+    fn readiness_interest(&self) -> Interest {
+        Interest::READABLE
+    }
+
+    fn read_changes(&mut self) -> Result<Option<TableChanges<LinuxMount>>, ReadError> {
+        self.update(MountStats::get, MountStats::all)
+    }
+}
+
+impl MountTableSource<MountInfo> for FanotifyTable<MountInfo> {
+    fn read(&mut self) -> Result<Vec<MountInfo>, ReadError> {
+        self.read_all(MountStats::get, MountStats::all)
+    }
+
+    fn readiness_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+
+    fn readiness_interest(&self) -> Interest {
+        Interest::READABLE
+    }
+
+    fn read_changes(&mut self) -> Result<Option<TableChanges<MountInfo>>, ReadError> {
+        self.update(MountStats::get, MountStats::all)
+    }
+}
+
+/// A mount notification of fanotify.
+#[derive(Debug, PartialEq, Eq)]
+enum Notification {
+    Attach(u64),
+    Detach(u64),
+    Overflow,
+}
+
+/// Parses the `fanotify_event_metadata` structures of `buf`, and their info records.
+fn parse_events(mut buf: &[u8], notifications: &mut Vec<Notification>) -> io::Result<()> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+    let u16_at = |b: &[u8], i: usize| u16::from_ne_bytes([b[i], b[i + 1]]);
+    let u32_at = |b: &[u8], i: usize| u32::from_ne_bytes(b[i..i + 4].try_into().unwrap());
+    let u64_at = |b: &[u8], i: usize| u64::from_ne_bytes(b[i..i + 8].try_into().unwrap());
+
+    while buf.len() >= 24 {
+        let event_len = u32_at(buf, 0) as usize;
+        let version = buf[4];
+        let metadata_len = u16_at(buf, 6) as usize;
+        if version != FANOTIFY_METADATA_VERSION {
+            return Err(invalid("unexpected fanotify metadata version"));
+        }
+        if event_len < metadata_len || event_len > buf.len() || metadata_len < 24 {
+            return Err(invalid("invalid fanotify event length"));
+        }
+        let mask = u64_at(buf, 8);
+        if mask & FAN_Q_OVERFLOW != 0 {
+            notifications.push(Notification::Overflow);
+        }
+
+        // struct fanotify_event_info_mnt: a header of 4 bytes, then the mount ID at offset 8
+        let mut info = &buf[metadata_len..event_len];
+        while info.len() >= 4 {
+            let info_type = info[0];
+            let info_len = u16_at(info, 2) as usize;
+            if info_len < 4 || info_len > info.len() {
+                return Err(invalid("invalid fanotify info length"));
+            }
+            if info_type == FAN_EVENT_INFO_TYPE_MNT && info_len >= 16 {
+                let id = u64_at(info, 8);
+                // both for a move, which detaches the mount before attaching it elsewhere
+                if mask & FAN_MNT_DETACH != 0 {
+                    notifications.push(Notification::Detach(id));
+                }
+                if mask & FAN_MNT_ATTACH != 0 {
+                    notifications.push(Notification::Attach(id));
+                }
+            }
+            info = &info[info_len..];
+        }
+        buf = &buf[event_len..];
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, os::fd::AsRawFd};
+
+    use pretty_assertions::assert_eq;
+
+    use super::{parse_events, FanotifyTable, Notification};
+    use crate::{
+        mount::{LinuxMount, MountEntry},
+        mountinfo::MountInfo,
+        statmount::StatmountTable,
+        table::{MountTableSource, TableChanges},
+        test_util::{in_mount_namespace, sys_mount, sys_umount},
+    };
+
+    fn event(mask: u64, mnt_id: Option<u64>) -> Vec<u8> {
+        let len: u32 = if mnt_id.is_some() { 40 } else { 24 };
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&len.to_ne_bytes());
+        buf.extend_from_slice(&[3, 0]);
+        buf.extend_from_slice(&24u16.to_ne_bytes());
+        buf.extend_from_slice(&mask.to_ne_bytes());
+        buf.extend_from_slice(&(-1i32).to_ne_bytes());
+        buf.extend_from_slice(&0i32.to_ne_bytes());
+        if let Some(id) = mnt_id {
+            buf.extend_from_slice(&[7, 0]);
+            buf.extend_from_slice(&16u16.to_ne_bytes());
+            buf.extend_from_slice(&[0; 4]);
+            buf.extend_from_slice(&id.to_ne_bytes());
+        }
+        buf
+    }
+
+    #[test]
+    fn parsing() {
+        let mut buf = event(0x0100_0000, Some(12));
+        buf.extend(event(0x0200_0000, Some(13)));
+        buf.extend(event(0x4000, None));
+        let mut notifications = Vec::new();
+        parse_events(&buf, &mut notifications).unwrap();
+        assert_eq!(
+            notifications,
+            vec![
+                Notification::Attach(12),
+                Notification::Detach(13),
+                Notification::Overflow
+            ]
+        );
+
+        buf[0] = 255;
+        parse_events(&buf, &mut notifications).unwrap_err();
+    }
+
+    #[test]
+    fn same_as_statmount() {
+        let Ok(mut table) = FanotifyTable::<LinuxMount>::new() else {
+            eprintln!("fanotify mount events not supported, skipping the test");
+            return;
+        };
+        let expected: HashSet<_> = StatmountTable::<LinuxMount>::new()
+            .unwrap()
+            .read()
+            .unwrap()
+            .into_iter()
+            .collect();
+        let actual: HashSet<_> = table.read().unwrap().into_iter().collect();
+        assert_eq!(expected, actual);
+
+        let mut table = FanotifyTable::<MountInfo>::new().unwrap();
+        let expected: HashSet<_> = StatmountTable::<MountInfo>::new()
+            .unwrap()
+            .read()
+            .unwrap()
+            .into_iter()
+            .collect();
+        let actual: HashSet<_> = table.read().unwrap().into_iter().collect();
+        assert_eq!(expected, actual);
+    }
+
+    fn wait_ready(table: &FanotifyTable) {
+        let mut fd = libc::pollfd {
+            fd: table.readiness_fd().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: the pointer is valid for the duration of the call
+        let res = unsafe { libc::poll(&mut fd, 1, 5000) };
+        assert_eq!(res, 1, "the table is not ready");
+    }
+
+    #[test]
+    fn attach_detach() {
+        in_mount_namespace(|| {
+            let Ok(mut table) = FanotifyTable::<LinuxMount>::new() else {
+                eprintln!("fanotify mount events not supported, skipping the test");
+                return;
+            };
+            let dir =
+                std::env::temp_dir().join(format!("mount-watcher-fanotify-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            // the first read gets the whole table
+            assert_eq!(table.read_changes().unwrap(), None);
+            assert!(table.read().unwrap().iter().all(|m| m.mount_point != dir));
+
+            sys_mount(Some("none"), &dir, Some("tmpfs"), 0);
+            wait_ready(&table);
+            let changes = table.read_changes().unwrap().unwrap();
+            assert!(changes.unmounted.is_empty());
+            let [mounted] = &changes.mounted[..] else {
+                panic!("unexpected changes: {changes:?}");
+            };
+            assert_eq!(mounted.mount_point, dir);
+            assert!(!mounted.options().read_only());
+
+            // a remount is not notified, the detach reports the mount as it was attached
+            sys_mount(None, &dir, None, libc::MS_REMOUNT | libc::MS_RDONLY);
+            sys_umount(&dir);
+            wait_ready(&table);
+            let expected = TableChanges {
+                mounted: Vec::new(),
+                unmounted: vec![mounted.clone()],
+            };
+            assert_eq!(table.read_changes().unwrap(), Some(expected));
+            assert!(table.read().unwrap().iter().all(|m| m.mount_point != dir));
+            std::fs::remove_dir(&dir).unwrap();
+        });
+    }
+}
@@ -0,0 +1,217 @@
+//! Filter the mounts that you're interested in.
+
+use std::{
+    ffi::OsString,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{mount::MountEntry, MountEvent};
+
+/// Selects the mounts that are reported to the callback of a [`MountWatcher`](crate::MountWatcher).
+///
+/// A mount matches the filter if it satisfies every criterion that has been set.
+/// When a criterion is set multiple times (e.g. two filesystem types), the mount must
+/// match at least one of the values, except for the options, which must all be present.
+///
+/// An empty filter matches every mount.
+///
+/// # Example
+///
+/// ```
+/// use mount_watcher::filter::MountFilter;
+///
+/// // ext4 or xfs filesystems, mounted under /mnt, read-only
+/// let filter = MountFilter::new()
+///     .fs_type("ext4")
+///     .fs_type("xfs")
+///     .mount_point_prefix("/mnt")
+///     .option("ro");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MountFilter {
+    fs_types: Vec<String>,
+    mount_points: Vec<PathPattern>,
+    specs: Vec<OsString>,
+    options: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+enum PathPattern {
+    Prefix(PathBuf),
+    Glob(Vec<u8>),
+}
+
+impl MountFilter {
+    /// Creates an empty filter, which matches every mount.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only keeps the filesystems of the given type.
+    pub fn fs_type(mut self, fs_type: impl Into<String>) -> Self {
+        self.fs_types.push(fs_type.into());
+        self
+    }
+
+    /// Only keeps the mounts that are located at `prefix`, or below.
+    ///
+    /// The comparison is done component by component: `/mnt` matches `/mnt/usb`
+    /// but not `/mnt2`.
+    pub fn mount_point_prefix(mut self, prefix: impl Into<PathBuf>) -> Self {
+        self.mount_points.push(PathPattern::Prefix(prefix.into()));
+        self
+    }
+
+    /// Only keeps the mounts whose mount point matches the glob `pattern`.
+    ///
+    /// `?` matches any character except `/`, `*` matches any sequence of characters
+    /// except `/`, and `**` matches any sequence of characters, including `/`.
+    pub fn mount_point_glob(mut self, pattern: impl AsRef<Path>) -> Self {
+        let pattern = pattern.as_ref().as_os_str().as_bytes().to_vec();
+        self.mount_points.push(PathPattern::Glob(pattern));
+        self
+    }
+
+    /// Only keeps the mounts of the given source, for instance `/dev/sda1`.
+    pub fn spec(mut self, spec: impl Into<OsString>) -> Self {
+        self.specs.push(spec.into());
+        self
+    }
+
+    /// Only keeps the mounts that have the given mount option, for instance `ro`.
+    pub fn option(mut self, option: impl Into<String>) -> Self {
+        self.options.push(option.into());
+        self
+    }
+
+    /// Returns `true` if the mount matches the filter.
+    pub fn matches<M: MountEntry>(&self, mount: &M) -> bool {
+        (self.fs_types.is_empty() || self.fs_types.iter().any(|t| t == mount.fs_type()))
+            && (self.mount_points.is_empty()
+                || self
+                    .mount_points
+                    .iter()
+                    .any(|p| p.matches(mount.mount_point())))
+            && (self.specs.is_empty() || self.specs.iter().any(|s| s == mount.spec()))
+            && self
+                .options
+                .iter()
+                .all(|o| mount.mount_options().contains(o))
+    }
+
+    /// Removes the mounts that don't match the filter from the event.
+    ///
+    /// A change is kept if the mount matches the filter before or after the change.
+    pub(crate) fn apply<M: MountEntry>(&self, event: &mut MountEvent<M>) {
+        event.mounted.retain(|m| self.matches(m));
+        event.unmounted.retain(|m| self.matches(m));
+        event
+            .changed
+            .retain(|c| self.matches(&c.old) || self.matches(&c.new));
+    }
+}
+
+impl PathPattern {
+    fn matches(&self, path: &Path) -> bool {
+        match self {
+            PathPattern::Prefix(prefix) => path.starts_with(prefix),
+            PathPattern::Glob(pattern) => glob_match(pattern, path.as_os_str().as_bytes()),
+        }
+    }
+}
+
+/// Matches `input` against a glob `pattern` (see [`MountFilter::mount_point_glob`]).
+///
+/// The table tells whether `pattern[p..]` matches `input[i..]`. Filling it from the end
+/// takes `pattern.len() * input.len()` steps, instead of backtracking on each `*`.
+fn glob_match(pattern: &[u8], input: &[u8]) -> bool {
+    let width = input.len() + 1;
+    let at = |p: usize, i: usize| p * width + i;
+    let mut matched = vec![false; (pattern.len() + 1) * width];
+    matched[at(pattern.len(), input.len())] = true;
+    for p in (0..pattern.len()).rev() {
+        for i in (0..=input.len()).rev() {
+            let c = input.get(i).copied();
+            matched[at(p, i)] = match pattern[p] {
+                b'*' if pattern.get(p + 1) == Some(&b'*') => {
+                    matched[at(p + 2, i)] || (c.is_some() && matched[at(p, i + 1)])
+                }
+                b'*' => {
+                    matched[at(p + 1, i)] || (c.is_some_and(|c| c != b'/') && matched[at(p, i + 1)])
+                }
+                b'?' => c.is_some_and(|c| c != b'/') && matched[at(p + 1, i + 1)],
+                b => c == Some(b) && matched[at(p + 1, i + 1)],
+            };
+        }
+    }
+    matched[at(0, 0)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, MountFilter};
+    use crate::test_util::mount;
+
+    #[test]
+    fn glob() {
+        assert!(glob_match(b"/mnt/*", b"/mnt/usb"));
+        assert!(!glob_match(b"/mnt/*", b"/mnt/usb/data"));
+        assert!(glob_match(b"/mnt/**", b"/mnt/usb/data"));
+        assert!(glob_match(b"/media/*/disk?", b"/media/user/disk1"));
+        assert!(!glob_match(b"/media/*/disk?", b"/media/user/disk"));
+        assert!(glob_match(
+            b"/var/lib/**/rootfs",
+            b"/var/lib/containers/x/rootfs"
+        ));
+        assert!(!glob_match(
+            b"/var/lib/**/rootfs",
+            b"/var/lib/containers/x/root"
+        ));
+        assert!(glob_match(b"/mnt/**", b"/mnt/"));
+        assert!(glob_match(b"/mnt/*", b"/mnt/"));
+        assert!(!glob_match(b"/mnt/?", b"/mnt/"));
+        assert!(glob_match(b"**", b""));
+    }
+
+    #[test]
+    fn glob_many_wildcards() {
+        // would backtrack for ages without memoization
+        let input = "/a".repeat(500);
+        assert!(!glob_match(b"/**/**/**/**/**/**/**/**/x", input.as_bytes()));
+        assert!(!glob_match(b"*/*/*/*/*/*/*/*/*/*/x", input.as_bytes()));
+        let input = format!("{input}/x");
+        assert!(glob_match(b"/**/**/**/**/**/**/**/**/x", input.as_bytes()));
+    }
+
+    #[test]
+    fn matching() {
+        let usb = mount("/dev/sdb1 /mnt/usb vfat ro,relatime 0 0");
+        let tmp = mount("tmpfs /tmp tmpfs rw 0 0");
+
+        assert!(MountFilter::new().matches(&usb));
+        assert!(MountFilter::new().matches(&tmp));
+
+        let filter = MountFilter::new().fs_type("ext4").fs_type("vfat");
+        assert!(filter.matches(&usb));
+        assert!(!filter.matches(&tmp));
+
+        let filter = MountFilter::new().mount_point_prefix("/mnt");
+        assert!(filter.matches(&usb));
+        assert!(!filter.matches(&tmp));
+        assert!(!MountFilter::new().mount_point_prefix("/mn").matches(&usb));
+
+        let filter = MountFilter::new().spec("tmpfs");
+        assert!(!filter.matches(&usb));
+        assert!(filter.matches(&tmp));
+
+        let filter = MountFilter::new().option("ro").option("relatime");
+        assert!(filter.matches(&usb));
+        assert!(!filter.matches(&tmp));
+
+        let filter = MountFilter::new()
+            .mount_point_glob("/mnt/*")
+            .fs_type("ext4");
+        assert!(!filter.matches(&usb));
+    }
+}
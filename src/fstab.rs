@@ -0,0 +1,700 @@
+//! Parse /etc/fstab, and compare it with the mounted filesystems.
+//!
+//! The entries of `fstab` have the same format as the lines of `/proc/mounts`, hence they
+//! are parsed as [`LinuxMount`]s. The filesystems are often designated by a tag such as
+//! `UUID=...` instead of a device path, see [`FsSpec`].
+//!
+//! [`drift`] reports the differences between the declared filesystems and the mounted ones.
+//!
+//! ```no_run
+//! let drift = mount_watcher::fstab::drift().unwrap();
+//! for entry in drift.not_mounted {
+//!     println!("{} is not mounted", entry.mount_point.display());
+//! }
+//! ```
+
+use std::{
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    fs,
+    fs::File,
+    io::{self, Read},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::{OsStrExt, OsStringExt},
+    },
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use mio::{unix::SourceFd, Interest, Registry, Token};
+
+use crate::{
+    device::DeviceResolver,
+    mount::{
+        list_current_mounts, parse_lines, Fields, LinuxMount, MountEntry, OnInvalidLine,
+        ParseError, ReadError,
+    },
+    options::{AtimeMode, MountOption, MountOptions},
+    watch::{diff_mounts, ErrorImpl},
+    MountChange,
+};
+
+pub const FSTAB_PATH: &str = "/etc/fstab";
+
+/// The options of fstab that are only used by `mount` and other programs, not by the kernel.
+const USERSPACE_OPTIONS: &[&str] = &[
+    "defaults", "auto", "noauto", "user", "nouser", "users", "owner", "group", "nofail", "_netdev",
+    "sw", "comment", "bind", "rbind",
+];
+
+/// The first field of an fstab entry, which designates the filesystem.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FsSpec {
+    /// `UUID=...`: the UUID of the filesystem.
+    Uuid(String),
+    /// `LABEL=...`: the label of the filesystem.
+    Label(String),
+    /// `PARTUUID=...`: the UUID of the partition (GPT).
+    PartUuid(String),
+    /// `PARTLABEL=...`: the label of the partition (GPT).
+    PartLabel(String),
+    /// An absolute path, usually a block device such as `/dev/sda1`.
+    Path(PathBuf),
+    /// Anything else, such as `tmpfs` or `server:/export`.
+    Other(OsString),
+}
+
+impl FsSpec {
+    /// Parses a spec, such as [`LinuxMount::spec`].
+    ///
+    /// The value of a tag can be quoted, like in `LABEL="My Disk"`.
+    pub fn parse(spec: &OsStr) -> Self {
+        let bytes = spec.as_bytes();
+        let tag = |prefix: &str| {
+            let value = bytes.strip_prefix(prefix.as_bytes())?;
+            let value = match value {
+                [b'"', inner @ .., b'"'] => inner,
+                _ => value,
+            };
+            String::from_utf8(value.to_vec()).ok()
+        };
+        if let Some(uuid) = tag("UUID=") {
+            FsSpec::Uuid(uuid)
+        } else if let Some(label) = tag("LABEL=") {
+            FsSpec::Label(label)
+        } else if let Some(uuid) = tag("PARTUUID=") {
+            FsSpec::PartUuid(uuid)
+        } else if let Some(label) = tag("PARTLABEL=") {
+            FsSpec::PartLabel(label)
+        } else if bytes.starts_with(b"/") {
+            FsSpec::Path(PathBuf::from(spec))
+        } else {
+            FsSpec::Other(spec.to_owned())
+        }
+    }
+}
+
+impl LinuxMount {
+    /// Parses the spec of the mount, see [`FsSpec::parse`].
+    pub fn fs_spec(&self) -> FsSpec {
+        FsSpec::parse(&self.spec)
+    }
+}
+
+/// Parses one line of fstab.
+///
+/// Unlike in `/proc/mounts`, the last two fields are optional and default to 0.
+fn parse_line(line: &[u8]) -> Result<LinuxMount, ParseError> {
+    let mut fields = Fields::new(line);
+    let raw_spec = fields.next_raw()?;
+    let raw_mount_point = fields.next_raw()?;
+    Ok(LinuxMount {
+        spec: fields.unescape(raw_spec, "spec")?,
+        mount_point: fields.unescape(raw_mount_point, "mount point")?.into(),
+        fs_type: fields.next_str("filesystem type")?,
+        mount_options: fields.next_list()?,
+        dump_fs_freq: fields.next_optional_number()?.unwrap_or(0),
+        fsck_fs_passno: fields.next_optional_number()?.unwrap_or(0),
+        raw_spec: OsString::from_vec(raw_spec.to_vec()),
+        raw_mount_point: OsString::from_vec(raw_mount_point.to_vec()),
+    })
+}
+
+/// Parses the content of an fstab file.
+pub fn parse_fstab(content: &[u8]) -> Result<Vec<LinuxMount>, ParseError> {
+    let mut entries = Vec::new();
+    parse_lines(
+        content,
+        &mut entries,
+        OnInvalidLine::Fail,
+        FSTAB_PATH,
+        parse_line,
+    )?;
+    Ok(entries)
+}
+
+/// Reads and parses `/etc/fstab`.
+pub fn read_fstab() -> Result<Vec<LinuxMount>, ReadError> {
+    read_fstab_file(FSTAB_PATH)
+}
+
+/// Reads and parses an fstab file.
+pub fn read_fstab_file(path: impl AsRef<Path>) -> Result<Vec<LinuxMount>, ReadError> {
+    let content = fs::read(path)?;
+    Ok(parse_fstab(&content)?)
+}
+
+/// The differences between fstab and the mounted filesystems, see [`drift`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Drift {
+    /// The entries of fstab that are not mounted.
+    ///
+    /// The entries with the `noauto` option are ignored.
+    pub not_mounted: Vec<LinuxMount>,
+    /// The mounts that are not declared in fstab.
+    ///
+    /// This includes the pseudo filesystems such as `/proc`, you may want to filter them
+    /// before calling [`compare`], for instance with a [`MountFilter`](crate::filter::MountFilter).
+    pub undeclared: Vec<LinuxMount>,
+    /// The mount points where another filesystem than the declared one is mounted: the
+    /// device or the filesystem type differs.
+    pub wrong_source: Vec<SourceDrift>,
+    /// The mounts whose device could not be compared with fstab, because it could not be
+    /// resolved (the error is logged). Their options are still compared.
+    pub unverified: Vec<SourceDrift>,
+    /// The mounts whose options differ from fstab.
+    pub options: Vec<OptionsDrift>,
+}
+
+/// A mount point where another filesystem than the fstab entry is mounted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceDrift {
+    /// The entry of fstab.
+    pub declared: LinuxMount,
+    /// The mount.
+    pub mounted: LinuxMount,
+}
+
+/// A mount whose options differ from its fstab entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionsDrift {
+    /// The entry of fstab.
+    pub declared: LinuxMount,
+    /// The mount.
+    pub mounted: LinuxMount,
+    /// The options of the fstab entry that don't apply to the mount, such as `ro` for
+    /// a read-write mount.
+    pub mismatched: Vec<String>,
+}
+
+impl Drift {
+    /// Returns `true` if the mounted filesystems match fstab.
+    pub fn is_empty(&self) -> bool {
+        self.not_mounted.is_empty()
+            && self.undeclared.is_empty()
+            && self.wrong_source.is_empty()
+            && self.unverified.is_empty()
+            && self.options.is_empty()
+    }
+}
+
+/// Compares `/etc/fstab` with `/proc/mounts`, see [`compare`].
+pub fn drift() -> Result<Drift, ReadError> {
+    let resolver = DeviceResolver::new();
+    Ok(compare(&read_fstab()?, &list_current_mounts()?, &resolver))
+}
+
+/// Compares the `declared` filesystems, from fstab, with the `mounted` ones.
+///
+/// The entries are matched by mount point. The swap areas are ignored.
+///
+/// A mount has the wrong source if its filesystem type is not the declared one, or if its
+/// spec designates another device: the tags such as `UUID=...` and the links such as
+/// `/dev/mapper/root` are resolved with `resolver`. The other specs, like `server:/export`,
+/// are compared as strings. The options of such a mount are not compared. The source and
+/// the type of a bind mount, with the `bind` or `rbind` option, are not checked: the mount
+/// shows the type and the device of the directory it comes from.
+///
+/// An option is mismatched if it is not in the mount options, except for the options
+/// that the kernel doesn't show, like `exec` (the mount is mismatched if it has `noexec`)
+/// or `nofail`. The values, such as `size=1G`, are compared as strings, although the kernel
+/// may show them differently (`size=1048576k`).
+pub fn compare(
+    declared: &[LinuxMount],
+    mounted: &[LinuxMount],
+    resolver: &DeviceResolver,
+) -> Drift {
+    let declared: Vec<&LinuxMount> = declared
+        .iter()
+        .filter(|e| e.fs_type != "swap" && e.mount_point.is_absolute())
+        .collect();
+    let mut drift = Drift::default();
+    for entry in &declared {
+        // If several filesystems are mounted at the same place, the last one is visible.
+        let mount = mounted
+            .iter()
+            .rev()
+            .find(|m| m.mount_point == entry.mount_point);
+        let Some(mount) = mount else {
+            if !entry.options().contains("noauto") {
+                drift.not_mounted.push((*entry).clone());
+            }
+            continue;
+        };
+        let source = || SourceDrift {
+            declared: (*entry).clone(),
+            mounted: mount.clone(),
+        };
+        let options = entry.options();
+        if !options.contains("bind") && !options.contains("rbind") {
+            match same_source(entry, mount, resolver) {
+                Ok(true) => (),
+                Ok(false) => {
+                    drift.wrong_source.push(source());
+                    continue;
+                }
+                Err(e) => {
+                    log::warn!(
+                        "cannot compare the device mounted at {:?} with fstab: {e}",
+                        mount.mount_point
+                    );
+                    drift.unverified.push(source());
+                }
+            }
+        }
+        let mismatched: Vec<String> = options
+            .iter()
+            .filter(|o| !option_applies(o, &mount.options()))
+            .map(|o| o.to_string())
+            .collect();
+        if !mismatched.is_empty() {
+            drift.options.push(OptionsDrift {
+                declared: (*entry).clone(),
+                mounted: mount.clone(),
+                mismatched,
+            });
+        }
+    }
+    drift.undeclared = mounted
+        .iter()
+        .filter(|m| !declared.iter().any(|e| e.mount_point == m.mount_point))
+        .cloned()
+        .collect();
+    drift
+}
+
+/// Returns `true` if the filesystem type of a mount is consistent with the `declared` one,
+/// which can be `auto` or a list like `ext4,ext3`.
+fn same_fs_type(declared: &str, mounted: &str) -> bool {
+    declared == "auto"
+        || declared
+            .split(',')
+            // mount.nfs negotiates the version
+            .any(|t| t == mounted || (t == "nfs" && mounted == "nfs4"))
+}
+
+/// Returns `true` if `mounted` has the type of the fstab `entry`, and if its spec designates
+/// the same filesystem.
+fn same_source(
+    entry: &LinuxMount,
+    mounted: &LinuxMount,
+    resolver: &DeviceResolver,
+) -> io::Result<bool> {
+    if !same_fs_type(&entry.fs_type, &mounted.fs_type) {
+        return Ok(false);
+    }
+    let (declared, mounted) = (entry.fs_spec(), mounted.fs_spec());
+    if declared == mounted {
+        return Ok(true);
+    }
+    if let FsSpec::Other(_) = declared {
+        return Ok(false);
+    }
+    let Some(declared) = resolver.resolve(&declared)? else {
+        return Ok(false);
+    };
+    Ok(resolver
+        .resolve(&mounted)?
+        .is_some_and(|m| (m.major, m.minor) == (declared.major, declared.minor)))
+}
+
+/// Returns `true` if the fstab `option` is consistent with the `mounted` options.
+fn option_applies(option: &MountOption, mounted: &MountOptions) -> bool {
+    if USERSPACE_OPTIONS.contains(&option.key) || option.key.starts_with("x-") {
+        return true;
+    }
+    match (option.key, option.value) {
+        ("ro", None) => mounted.read_only(),
+        ("rw", None) => !mounted.read_only(),
+        ("exec", None) => !mounted.noexec(),
+        ("suid", None) => !mounted.nosuid(),
+        ("dev", None) => !mounted.nodev(),
+        ("async", None) => !mounted.contains("sync"),
+        ("atime", None) => mounted.atime() != AtimeMode::Noatime,
+        ("relatime", None) => mounted.atime() == AtimeMode::Relatime,
+        ("noatime", None) => mounted.atime() == AtimeMode::Noatime,
+        ("strictatime", None) => mounted.atime() == AtimeMode::Strictatime,
+        (key, None) => mounted.contains(key),
+        (key, Some(value)) => mounted.get(key) == Some(value),
+    }
+}
+
+/// A change of fstab, see [`MountEvent::fstab`](crate::MountEvent::fstab).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FstabEvent {
+    /// The entries that have been added.
+    pub added: Vec<LinuxMount>,
+    /// The entries that have been removed.
+    pub removed: Vec<LinuxMount>,
+    /// The entries whose options, or other fields, have been modified.
+    ///
+    /// An entry is modified if its spec and mount point are the same.
+    pub changed: Vec<MountChange<LinuxMount>>,
+    /// All the entries of the new fstab.
+    pub entries: Vec<LinuxMount>,
+}
+
+/// How long to wait after a modification of fstab before reading it, because editors
+/// often write the file in several steps.
+pub(crate) const FSTAB_DELAY: Duration = Duration::from_millis(100);
+
+const IN_EVENTS: u32 = libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_TO
+    | libc::IN_MOVED_FROM
+    | libc::IN_CREATE
+    | libc::IN_DELETE;
+
+/// Watches an fstab file with inotify, in the polling loop of a watcher.
+///
+/// The directory of the file is watched, instead of the file itself, because editors
+/// usually write a new file and rename it over the old one. If the path is a symlink, the
+/// directory of its target is watched too, and the link is resolved again on each read
+/// in case it has been changed.
+///
+/// The changes are reported in the events of the mount table, see [`State`](crate::watch::State).
+pub(crate) struct FstabWatch {
+    inotify: File,
+    /// The path given to the builder, which may be a symlink.
+    path: PathBuf,
+    /// The watch descriptor of each watched directory, with the name of the file in it.
+    files: Vec<(i32, OsString)>,
+    /// The entries that have been reported.
+    entries: HashSet<LinuxMount>,
+    /// Set when the file has been modified, until its changes are reported.
+    modified: bool,
+    buf: Vec<u8>,
+}
+
+impl FstabWatch {
+    pub(crate) fn new(path: PathBuf) -> Result<Self, ErrorImpl> {
+        if path.file_name().is_none() {
+            let e = io::Error::new(io::ErrorKind::InvalidInput, "not a file path");
+            return Err(ErrorImpl::FstabRead(path, ReadError::Io(e)));
+        }
+
+        // SAFETY: no pointer is involved
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(ErrorImpl::Inotify(io::Error::last_os_error()));
+        }
+        // SAFETY: the fd has just been created, and nothing else owns it
+        let inotify = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        let entries = read_fstab_file(&path)
+            .map_err(|e| ErrorImpl::FstabRead(path.clone(), e))?
+            .into_iter()
+            .collect();
+        let mut watch = Self {
+            inotify,
+            path,
+            files: Vec::new(),
+            entries,
+            modified: false,
+            buf: vec![0; 4096],
+        };
+        watch.watch_files().map_err(ErrorImpl::Inotify)?;
+        Ok(watch)
+    }
+
+    /// Watches the directory of the file, and the directory of its target if it is a symlink.
+    ///
+    /// The directories that are no longer needed, because the link has changed, are unwatched.
+    fn watch_files(&mut self) -> io::Result<()> {
+        let mut paths = vec![self.path.clone()];
+        if let Ok(target) = self.path.canonicalize() {
+            if target != self.path {
+                paths.push(target);
+            }
+        }
+        let mut files = Vec::with_capacity(paths.len());
+        for path in &paths {
+            let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+                continue;
+            };
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            let c_dir = std::ffi::CString::new(dir.as_os_str().as_bytes())?;
+            // SAFETY: c_dir is a valid C string
+            let wd = unsafe {
+                libc::inotify_add_watch(self.inotify.as_raw_fd(), c_dir.as_ptr(), IN_EVENTS)
+            };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            files.push((wd, file_name.to_owned()));
+        }
+        for (wd, _) in &self.files {
+            if !files.iter().any(|(new_wd, _)| new_wd == wd) {
+                // SAFETY: no pointer is involved
+                unsafe { libc::inotify_rm_watch(self.inotify.as_raw_fd(), *wd) };
+            }
+        }
+        self.files = files;
+        Ok(())
+    }
+
+    pub(crate) fn register(&self, registry: &Registry, token: Token) -> Result<(), ErrorImpl> {
+        let fd = self.inotify.as_raw_fd();
+        registry
+            .register(&mut SourceFd(&fd), token, Interest::READABLE)
+            .map_err(ErrorImpl::PollInit)
+    }
+
+    /// Reads the inotify events, and returns `true` if fstab has been modified.
+    pub(crate) fn read_notifications(&mut self) -> Result<bool, ErrorImpl> {
+        loop {
+            let n = match (&self.inotify).read(&mut self.buf) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(ErrorImpl::Inotify(e)),
+            };
+            self.modified |= contains_file(&self.buf[..n], &self.files);
+        }
+        Ok(self.modified)
+    }
+
+    /// Reads fstab if it has been modified, and returns its new entries if they have changed.
+    ///
+    /// The changes are reported again until they are [committed](Self::commit).
+    pub(crate) fn read_changes(
+        &mut self,
+    ) -> Result<Option<(HashSet<LinuxMount>, FstabEvent)>, ErrorImpl> {
+        if !self.modified {
+            return Ok(None);
+        }
+        // the symlink may point to another file now
+        self.watch_files().map_err(ErrorImpl::Inotify)?;
+        let entries: HashSet<LinuxMount> = match read_fstab_file(&self.path) {
+            Ok(entries) => entries.into_iter().collect(),
+            Err(ReadError::Io(e)) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => {
+                // probably a mistake in the file, wait for the next version
+                log::warn!("failed to read {:?}: {e:?}", self.path);
+                self.modified = false;
+                return Ok(None);
+            }
+        };
+        if entries == self.entries {
+            // saved without any change
+            self.modified = false;
+            return Ok(None);
+        }
+        let Some(diff) = diff_mounts(&self.entries, &entries, false, false) else {
+            self.modified = false;
+            return Ok(None);
+        };
+        let event = FstabEvent {
+            added: diff.mounted,
+            removed: diff.unmounted,
+            changed: diff.changed,
+            entries: entries.iter().cloned().collect(),
+        };
+        Ok(Some((entries, event)))
+    }
+
+    /// Saves the entries returned by [`read_changes`](Self::read_changes), once they have been
+    /// reported.
+    pub(crate) fn commit(&mut self, entries: HashSet<LinuxMount>) {
+        self.entries = entries;
+        self.modified = false;
+    }
+}
+
+/// Returns `true` if an event of the inotify buffer `buf` concerns one of the `files`,
+/// given by watch descriptor and name.
+fn contains_file(mut buf: &[u8], files: &[(i32, OsString)]) -> bool {
+    const HEADER_LEN: usize = std::mem::size_of::<libc::inotify_event>();
+    let mut found = false;
+    while buf.len() >= HEADER_LEN {
+        // SAFETY: the kernel writes whole inotify_event structures, possibly unaligned in buf
+        let event: libc::inotify_event =
+            unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const libc::inotify_event) };
+        let end = HEADER_LEN + event.len as usize;
+        let Some(raw_name) = buf.get(HEADER_LEN..end) else {
+            break;
+        };
+        // the name is padded with null bytes
+        let event_name = raw_name.split(|b| *b == 0).next().unwrap_or_default();
+        found |= event.mask & libc::IN_Q_OVERFLOW != 0
+            || files
+                .iter()
+                .any(|(wd, name)| *wd == event.wd && event_name == name.as_bytes());
+        buf = &buf[end..];
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, fs, os::unix::fs::symlink, path::PathBuf};
+
+    use pretty_assertions::assert_eq;
+
+    use super::{compare, parse_fstab, FsSpec, OptionsDrift, SourceDrift};
+    use crate::{device::DeviceResolver, test_util::mount};
+
+    #[test]
+    fn parsing() {
+        let content = b"
+# <file system> <mount point> <type> <options> <dump> <pass>
+UUID=0a3407de-014b-458b-b5c1-848e92a327a3 /  ext4  errors=remount-ro 0 1
+LABEL=My\\040Disk\t/media/My\\040Disk\tvfat\tnoauto,user
+/dev/sdb1 none swap sw 0 0
+server:/export /mnt/nfs nfs defaults,_netdev 0 0
+";
+        let entries = parse_fstab(content).unwrap();
+        assert_eq!(entries.len(), 4);
+
+        assert_eq!(
+            entries[0].fs_spec(),
+            FsSpec::Uuid("0a3407de-014b-458b-b5c1-848e92a327a3".into())
+        );
+        assert_eq!(entries[0].mount_point, PathBuf::from("/"));
+        assert_eq!(entries[0].fsck_fs_passno, 1);
+
+        assert_eq!(entries[1].fs_spec(), FsSpec::Label("My Disk".into()));
+        assert_eq!(entries[1].mount_point, PathBuf::from("/media/My Disk"));
+        assert_eq!(entries[1].raw_mount_point, "/media/My\\040Disk");
+        assert_eq!(entries[1].mount_options, ["noauto", "user"]);
+        assert_eq!(entries[1].dump_fs_freq, 0);
+        assert_eq!(entries[1].fsck_fs_passno, 0);
+
+        assert_eq!(
+            entries[2].fs_spec(),
+            FsSpec::Path(PathBuf::from("/dev/sdb1"))
+        );
+        assert_eq!(entries[3].fs_spec(), FsSpec::Other("server:/export".into()));
+
+        parse_fstab(b"/dev/sda1 /mnt").unwrap_err();
+        parse_fstab(b"/dev/sda1 /mnt ext4 rw x").unwrap_err();
+    }
+
+    #[test]
+    fn spec_parsing() {
+        assert_eq!(
+            FsSpec::parse(OsStr::new("LABEL=\"data\"")),
+            FsSpec::Label("data".into())
+        );
+        assert_eq!(
+            FsSpec::parse(OsStr::new("PARTUUID=1234-01")),
+            FsSpec::PartUuid("1234-01".into())
+        );
+        assert_eq!(
+            FsSpec::parse(OsStr::new("PARTLABEL=root")),
+            FsSpec::PartLabel("root".into())
+        );
+        assert_eq!(
+            FsSpec::parse(OsStr::new("tmpfs")),
+            FsSpec::Other("tmpfs".into())
+        );
+    }
+
+    #[test]
+    fn drift() {
+        let declared = parse_fstab(
+            b"
+UUID=abcd / ext4 errors=remount-ro,exec 0 1
+/dev/sdb1 none swap sw 0 0
+/dev/sdc1 /data ext4 ro,noatime,nofail,x-systemd.automount 0 2
+/dev/sdd1 /backup ext4 defaults 0 2
+/dev/sde1 /media/usb vfat noauto 0 0
+tmpfs /tmp tmpfs size=1G,mode=1777 0 0
+LABEL=archive /archive ext4 defaults 0 2
+/dev/sdf1 /srv auto defaults 0 2
+/dev/sdg1 /home ext4,xfs defaults 0 2
+/srv/data /mnt/data none bind 0 0
+UUID=bad /bad ext4 defaults 0 2
+",
+        )
+        .unwrap();
+        let mounted: Vec<_> = [
+            "/dev/sda1 / ext4 rw,relatime,errors=remount-ro 0 0",
+            "proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0",
+            "/dev/sdc1 /data ext4 rw,noatime 0 0",
+            "tmpfs /tmp tmpfs rw,nosuid,size=1G,mode=1777 0 0",
+            // the wrong disk, then the wrong filesystem type
+            "/dev/sdc1 /archive ext4 rw 0 0",
+            "/dev/sdf1 /srv btrfs rw 0 0",
+            "/dev/sdg1 /home vfat rw 0 0",
+            // a bind mount has the type of its source
+            "/dev/sda1 /mnt/data ext4 rw,relatime 0 0",
+            "/dev/sda1 /bad ext4 rw 0 0",
+        ]
+        .iter()
+        .map(|line| mount(line))
+        .collect();
+
+        // sda1 has the UUID abcd, and sdb1 the label archive
+        let root = std::env::temp_dir().join(format!("mount-watcher-drift-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (name, dev) in [("sda1", "8:1"), ("sdb1", "8:17"), ("sdc1", "8:33")] {
+            fs::create_dir_all(root.join("sys/class/block").join(name)).unwrap();
+            fs::write(root.join("sys/class/block").join(name).join("dev"), dev).unwrap();
+        }
+        fs::create_dir_all(root.join("dev/disk/by-uuid")).unwrap();
+        for name in ["sda1", "sdb1", "sdc1"] {
+            fs::write(root.join("dev").join(name), "").unwrap();
+        }
+        fs::create_dir_all(root.join("dev/disk/by-label")).unwrap();
+        symlink("../../sda1", root.join("dev/disk/by-uuid/abcd")).unwrap();
+        symlink("../../sdb1", root.join("dev/disk/by-label/archive")).unwrap();
+        // the device number of sdx1 cannot be read
+        fs::create_dir_all(root.join("sys/class/block/sdx1/dev")).unwrap();
+        fs::write(root.join("dev/sdx1"), "").unwrap();
+        symlink("../../sdx1", root.join("dev/disk/by-uuid/bad")).unwrap();
+        let resolver = DeviceResolver::with_root(&root);
+
+        let drift = compare(&declared, &mounted, &resolver);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(drift.not_mounted, [declared[3].clone()]);
+        assert_eq!(drift.undeclared, [mounted[1].clone()]);
+        assert_eq!(
+            drift.wrong_source,
+            [(6, 4), (8, 6)].map(|(d, m)| SourceDrift {
+                declared: declared[d].clone(),
+                mounted: mounted[m].clone(),
+            })
+        );
+        assert_eq!(
+            drift.unverified,
+            [SourceDrift {
+                declared: declared[10].clone(),
+                mounted: mounted[8].clone(),
+            }]
+        );
+        assert_eq!(
+            drift.options,
+            [OptionsDrift {
+                declared: declared[2].clone(),
+                mounted: mounted[2].clone(),
+                mismatched: vec!["ro".into()],
+            }]
+        );
+        assert!(!drift.is_empty());
+    }
+}
@@ -0,0 +1,794 @@
+//! Many callbacks on a single thread.
+//!
+//! Each [`MountWatcher`](crate::MountWatcher) has its own background thread. If you need
+//! many callbacks, for instance one per container, subscribe them to a [`MountWatchHub`]
+//! instead: it runs a single polling loop, reads each mount table once per change,
+//! and gives the changes to every subscriber of this table.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use mount_watcher::{filter::MountFilter, hub::MountWatchHub, MountWatcher, WatchControl};
+//!
+//! let hub = MountWatchHub::new().unwrap();
+//! let usb = hub
+//!     .subscribe(
+//!         MountWatcher::builder().filter(MountFilter::new().mount_point_prefix("/media")),
+//!         |event| {
+//!             println!("new USB drives: {:?}", event.mounted);
+//!             WatchControl::Continue
+//!         },
+//!     )
+//!     .unwrap();
+//! let container = hub
+//!     .subscribe(MountWatcher::builder().pid(1234), |event| {
+//!         println!("new mounts in the container: {:?}", event.mounted);
+//!         WatchControl::Continue
+//!     })
+//!     .unwrap();
+//!
+//! // stop one subscriber, the other one keeps running
+//! usb.stop().unwrap();
+//! ```
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    convert::Infallible,
+    fs::File,
+    io::ErrorKind,
+    os::{
+        fd::{AsRawFd, OwnedFd},
+        unix::fs::MetadataExt,
+    },
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle, ThreadId},
+};
+
+use mio::{unix::SourceFd, Events, Poll, Registry, Token, Waker};
+
+use crate::{
+    builder::{MountWatcherBuilder, TableLocation},
+    callback::try_coalesce,
+    mount::{LinuxMount, MountEntry, ReadError},
+    table::{register_source, MountTableSource, TableChanges},
+    watch::{
+        open_table, resolve_backing_path, CallbackError, ErrorImpl, JoinError, MountSnapshot,
+        OpenTable, ReadMounts, SetupError, State, StateOptions, StopError, TimerTokens, Trigger,
+        WatchError, WatchedProcess, POLL_TIMEOUT,
+    },
+    MountEvent, WatchControl,
+};
+
+const WAKER_TOKEN: Token = Token(0);
+
+/// Runs the callbacks of many subscribers on a single background thread.
+///
+/// Each subscriber is configured with its own [`MountWatcherBuilder`]: it has its own filter,
+/// coalescing delay, error policy, etc. The subscribers that watch the same mount table
+/// (the same path, pid or mount namespace) share the reads of this table.
+/// The [`source`](MountWatcherBuilder::source) of a subscriber is never shared.
+///
+/// The callbacks are called one after the other: a slow callback delays the others.
+/// If a callback panics, the hub stops, unless [`catch_panics`](MountWatcherBuilder::catch_panics)
+/// is enabled for this subscriber.
+///
+/// The hub stops when it is dropped, which stops all the subscriptions.
+pub struct MountWatchHub {
+    thread_handle: Option<JoinHandle<Result<(), WatchError>>>,
+    handle: HubHandle,
+}
+
+/// Handle of a subscriber of a [`MountWatchHub`].
+///
+/// The subscriber is removed from the hub when the `Subscription` is dropped.
+pub struct Subscription<M = LinuxMount> {
+    id: u64,
+    hub: HubHandle,
+    shared: Arc<Shared<M>>,
+}
+
+/// Sends commands to the polling loop of the hub.
+#[derive(Clone)]
+struct HubHandle {
+    commands: mpsc::Sender<Command>,
+    waker: Arc<Waker>,
+    /// The thread of the polling loop, which runs the callbacks.
+    thread: ThreadId,
+}
+
+enum Command {
+    Subscribe(Box<dyn FnOnce(&mut HubLoop) + Send>),
+    Unsubscribe(u64),
+    Stop,
+}
+
+/// State of a subscriber, shared with its `Subscription`.
+struct Shared<M> {
+    snapshot: Arc<Mutex<MountSnapshot<M>>>,
+    /// Set when the subscriber is removed from the hub.
+    result: Mutex<Option<Result<(), WatchError>>>,
+    finished: Condvar,
+}
+
+type BoxedCallback<M> = Box<dyn FnMut(MountEvent<M>) -> Result<WatchControl, CallbackError> + Send>;
+
+static NEXT_SUBSCRIBER_ID: AtomicU64 = AtomicU64::new(0);
+
+impl MountWatchHub {
+    /// Starts the background thread of the hub, without any subscriber.
+    pub fn new() -> Result<Self, SetupError> {
+        let poll = Poll::new().map_err(|e| SetupError(ErrorImpl::PollInit(e)))?;
+        let waker = Waker::new(poll.registry(), WAKER_TOKEN)
+            .map_err(|e| SetupError(ErrorImpl::PollInit(e)))?;
+        let (tx, rx) = mpsc::channel();
+
+        let mut hub_loop = HubLoop {
+            poll,
+            commands: rx,
+            groups: HashMap::new(),
+            shared_tables: HashMap::new(),
+            tokens: HashMap::new(),
+            next_token: WAKER_TOKEN.0 + 1,
+            next_group: 0,
+            stopped: false,
+        };
+        let thread_handle = std::thread::Builder::new()
+            .name(String::from("mount-watch-hub"))
+            .spawn(move || {
+                hub_loop.run().map_err(|e| {
+                    log::error!("error in the polling loop of the hub: {e:?}");
+                    WatchError(e)
+                })
+            })
+            .map_err(|e| SetupError(ErrorImpl::ThreadSpawn(e)))?;
+
+        Ok(Self {
+            handle: HubHandle {
+                commands: tx,
+                waker: Arc::new(waker),
+                thread: thread_handle.thread().id(),
+            },
+            thread_handle: Some(thread_handle),
+        })
+    }
+
+    /// Adds a subscriber, configured by `builder`, that executes the `callback` when
+    /// its mount table changes.
+    ///
+    /// The [`thread_name`](MountWatcherBuilder::thread_name) and the
+    /// [`stack_size`](MountWatcherBuilder::stack_size) of the builder are ignored.
+    /// [`watch_fstab`](MountWatcherBuilder::watch_fstab) is not supported: an error is returned.
+    ///
+    /// This waits for the hub thread to add the subscriber, hence it cannot be called from
+    /// a callback of the hub: in this case, an error is returned.
+    pub fn subscribe<M: MountEntry, S>(
+        &self,
+        builder: MountWatcherBuilder<M, S>,
+        callback: impl FnMut(MountEvent<M>) -> WatchControl + Send + 'static,
+    ) -> Result<Subscription<M>, SetupError> {
+        let mut callback = callback;
+        self.try_subscribe(builder, move |event| Ok::<_, Infallible>(callback(event)))
+    }
+
+    /// Like [`subscribe`](Self::subscribe), with a callback that can fail.
+    ///
+    /// See [`MountWatcher::try_new`](crate::MountWatcher::try_new).
+    pub fn try_subscribe<M: MountEntry, S, E: Into<CallbackError>>(
+        &self,
+        builder: MountWatcherBuilder<M, S>,
+        callback: impl FnMut(MountEvent<M>) -> Result<WatchControl, E> + Send + 'static,
+    ) -> Result<Subscription<M>, SetupError> {
+        self.handle.check_thread().map_err(SetupError)?;
+        if builder.fstab.is_some() {
+            return Err(SetupError(ErrorImpl::HubFstab));
+        }
+        let mut callback = callback;
+        let callback = move |event| callback(event).map_err(Into::into);
+        let callback: BoxedCallback<M> = match builder.coalesce {
+            Some((delay, initial)) => Box::new(try_coalesce(delay, initial, callback)),
+            None => Box::new(callback),
+        };
+
+        let (key, location) = table_key::<M>(builder.source.is_some(), builder.table)?;
+        let options = StateOptions {
+            filter: builder.filter,
+            backing_path: resolve_backing_path::<M>(
+                builder.backing_path,
+                location.as_ref(),
+                builder.source.is_some(),
+            )
+            .map_err(SetupError)?,
+            initial_event: builder.initial_event,
+            error_policy: builder.error_policy,
+            error_hook: builder.error_hook,
+            catch_panics: builder.catch_panics,
+            fstab: None,
+        };
+        let subscriber = NewSubscriber {
+            id: NEXT_SUBSCRIBER_ID.fetch_add(1, Ordering::Relaxed),
+            key,
+            source: builder.source,
+            location,
+            options,
+            shared: Arc::new(Shared {
+                snapshot: Arc::new(Mutex::new(MountSnapshot::empty())),
+                result: Mutex::new(None),
+                finished: Condvar::new(),
+            }),
+            callback,
+        };
+        let id = subscriber.id;
+        let shared = Arc::clone(&subscriber.shared);
+
+        // The table is opened by the polling loop, unless it is already watched.
+        let (reply_tx, reply_rx) = mpsc::sync_channel(1);
+        let command = Command::Subscribe(Box::new(move |hub: &mut HubLoop| {
+            match hub.subscribe(subscriber) {
+                Ok(group_id) => {
+                    let _ = reply_tx.send(Ok(()));
+                    // Like a MountWatcher, start with the current mounts.
+                    hub.handle(group_id, Target::Subscriber(id, Trigger::Mount));
+                }
+                Err(e) => {
+                    let _ = reply_tx.send(Err(e));
+                }
+            }
+        }));
+        self.handle.send(command).map_err(SetupError)?;
+        reply_rx
+            .recv()
+            .map_err(|_| SetupError(ErrorImpl::HubStopped))?
+            .map_err(SetupError)?;
+
+        Ok(Subscription {
+            id,
+            hub: self.handle.clone(),
+            shared,
+        })
+    }
+
+    /// Requests the background thread to terminate, which stops all the subscriptions.
+    ///
+    /// To wait for the termination, use [`join`](Self::join).
+    pub fn stop(&self) -> Result<(), StopError> {
+        self.handle.send(Command::Stop).map_err(StopError)
+    }
+
+    /// Waits for the background thread to terminate.
+    ///
+    /// See [`MountWatcher::join`](crate::MountWatcher::join).
+    pub fn join(mut self) -> Result<(), JoinError> {
+        let handle = self.thread_handle.take().unwrap();
+        match handle.join() {
+            Ok(res) => Ok(res?),
+            Err(payload) => Err(JoinError::Panicked(payload)),
+        }
+    }
+}
+
+impl Drop for MountWatchHub {
+    fn drop(&mut self) {
+        if self.thread_handle.is_some() {
+            // the thread may have stopped already
+            let _ = self.stop();
+        }
+    }
+}
+
+impl<M> Subscription<M> {
+    /// Returns the mounts that are known by this subscriber.
+    ///
+    /// See [`MountWatcher::snapshot`](crate::MountWatcher::snapshot).
+    pub fn snapshot(&self) -> MountSnapshot<M> {
+        self.shared.snapshot.lock().unwrap().clone()
+    }
+
+    /// Removes the subscriber from the hub. The other subscribers are not affected.
+    ///
+    /// To wait for the removal, use [`join`](Self::join).
+    pub fn stop(&self) -> Result<(), StopError> {
+        self.hub
+            .send(Command::Unsubscribe(self.id))
+            .map_err(StopError)
+    }
+
+    /// Returns `true` if the subscriber has been removed from the hub.
+    pub fn is_finished(&self) -> bool {
+        self.shared.result.lock().unwrap().is_some()
+    }
+
+    /// Waits for the subscriber to be removed from the hub, because it has been stopped,
+    /// because its callback has returned [`WatchControl::Stop`], or because of an error.
+    ///
+    /// If the hub has stopped because of an error, this returns an error too.
+    ///
+    /// The subscriber is removed by the hub thread, hence this cannot wait from a callback
+    /// of the hub: in this case, an error is returned if the subscriber is still running.
+    pub fn join(self) -> Result<(), WatchError> {
+        let mut result = self.shared.result.lock().unwrap();
+        loop {
+            match result.take() {
+                // put a value back, so that the drop of `self` does nothing
+                Some(res) => {
+                    *result = Some(Ok(()));
+                    return res;
+                }
+                None => {
+                    self.hub.check_thread().map_err(WatchError)?;
+                    result = self.shared.finished.wait(result).unwrap();
+                }
+            }
+        }
+    }
+}
+
+impl<M> Drop for Subscription<M> {
+    fn drop(&mut self) {
+        if !self.is_finished() {
+            // the hub may have stopped already
+            let _ = self.stop();
+        }
+    }
+}
+
+impl<M> Shared<M> {
+    fn finish(&self, result: Result<(), WatchError>) {
+        *self.result.lock().unwrap() = Some(result);
+        self.finished.notify_all();
+    }
+}
+
+impl HubHandle {
+    /// Fails if called from the hub thread, which would wait for itself.
+    fn check_thread(&self) -> Result<(), ErrorImpl> {
+        if thread::current().id() == self.thread {
+            return Err(ErrorImpl::HubThread);
+        }
+        Ok(())
+    }
+
+    fn send(&self, command: Command) -> Result<(), ErrorImpl> {
+        self.commands
+            .send(command)
+            .map_err(|_| ErrorImpl::HubStopped)?;
+        self.waker.wake().map_err(ErrorImpl::Stop)
+    }
+}
+
+/// Identifies a mount table that can be shared by several subscribers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TableKey {
+    entry: TypeId,
+    location: KeyLocation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum KeyLocation {
+    Default,
+    Path(PathBuf),
+    Pid(u32),
+    Namespace { dev: u64, ino: u64 },
+}
+
+/// Returns the key of the table of a subscriber, or `None` if it has its own source.
+fn table_key<M: MountEntry>(
+    has_source: bool,
+    location: Option<TableLocation>,
+) -> Result<(Option<TableKey>, Option<TableLocation>), SetupError> {
+    if has_source {
+        return Ok((None, location));
+    }
+    let (key, location) = match location {
+        None => (KeyLocation::Default, None),
+        Some(TableLocation::Path(path)) => (
+            KeyLocation::Path(path.clone()),
+            Some(TableLocation::Path(path)),
+        ),
+        Some(TableLocation::Pid(pid)) => (KeyLocation::Pid(pid), Some(TableLocation::Pid(pid))),
+        Some(TableLocation::Namespace(ns)) => {
+            // two file descriptors of the same namespace refer to the same inode
+            let ns = File::from(ns);
+            let metadata = ns
+                .metadata()
+                .map_err(|e| SetupError(ErrorImpl::MountRead(ReadError::Io(e))))?;
+            let key = KeyLocation::Namespace {
+                dev: metadata.dev(),
+                ino: metadata.ino(),
+            };
+            (key, Some(TableLocation::Namespace(OwnedFd::from(ns))))
+        }
+    };
+    let key = TableKey {
+        entry: TypeId::of::<M>(),
+        location: key,
+    };
+    Ok((Some(key), location))
+}
+
+/// A subscriber that has not been added to the polling loop yet.
+struct NewSubscriber<M: MountEntry> {
+    id: u64,
+    key: Option<TableKey>,
+    source: Option<Box<dyn MountTableSource<M>>>,
+    location: Option<TableLocation>,
+    options: StateOptions,
+    shared: Arc<Shared<M>>,
+    callback: BoxedCallback<M>,
+}
+
+/// What a token of the polling loop refers to, in a group.
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Table,
+    Process,
+    Subscriber(u64, Trigger),
+}
+
+struct HubLoop {
+    poll: Poll,
+    commands: mpsc::Receiver<Command>,
+    groups: HashMap<u64, GroupEntry>,
+    /// The groups whose table can be shared.
+    shared_tables: HashMap<TableKey, u64>,
+    tokens: HashMap<Token, (u64, Target)>,
+    next_token: usize,
+    next_group: u64,
+    /// Set when the hub is stopped on purpose.
+    stopped: bool,
+}
+
+struct GroupEntry {
+    group: Box<dyn Group>,
+    key: Option<TableKey>,
+    /// The tokens of the table and of the process.
+    tokens: Vec<Token>,
+}
+
+impl HubLoop {
+    fn run(&mut self) -> Result<(), ErrorImpl> {
+        let mut events = Events::with_capacity(32);
+        loop {
+            if let Err(e) = self.poll.poll(&mut events, Some(POLL_TIMEOUT)) {
+                if e.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(ErrorImpl::PollPoll(e));
+            }
+            for event in events.iter() {
+                log::debug!("hub event: {event:?}");
+                if event.token() == WAKER_TOKEN {
+                    if !self.handle_commands() {
+                        self.stopped = true;
+                        return Ok(());
+                    }
+                } else if let Some(&(group_id, target)) = self.tokens.get(&event.token()) {
+                    self.handle(group_id, target);
+                }
+            }
+        }
+    }
+
+    /// Returns `false` if the hub must stop.
+    fn handle_commands(&mut self) -> bool {
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                Command::Subscribe(f) => f(self),
+                Command::Unsubscribe(id) => self.unsubscribe(id),
+                Command::Stop => return false,
+            }
+        }
+        true
+    }
+
+    fn new_token(&mut self, group_id: u64, target: Target) -> Token {
+        let token = Token(self.next_token);
+        self.next_token += 1;
+        self.tokens.insert(token, (group_id, target));
+        token
+    }
+
+    /// Adds a subscriber to the group of its table, which is created if needed.
+    fn subscribe<M: MountEntry>(&mut self, subscriber: NewSubscriber<M>) -> Result<u64, ErrorImpl> {
+        let existing = subscriber
+            .key
+            .as_ref()
+            .and_then(|key| self.shared_tables.get(key).copied());
+        let group_id = match existing {
+            Some(group_id) => group_id,
+            None => {
+                let OpenTable { table, process } =
+                    open_table(subscriber.source, subscriber.location)?;
+                let group_id = self.next_group;
+                let mut tokens = vec![self.new_token(group_id, Target::Table)];
+                let registered = register_source(table.as_ref(), self.poll.registry(), tokens[0])
+                    .map_err(ErrorImpl::PollInit)
+                    .and_then(|()| match &process {
+                        Some(process) => {
+                            tokens.push(self.new_token(group_id, Target::Process));
+                            process.register(self.poll.registry(), tokens[1])
+                        }
+                        None => Ok(()),
+                    });
+                if let Err(e) = registered {
+                    for token in tokens {
+                        self.tokens.remove(&token);
+                    }
+                    return Err(e);
+                }
+
+                self.next_group += 1;
+                if let Some(key) = &subscriber.key {
+                    self.shared_tables.insert(key.clone(), group_id);
+                }
+                let group = TableGroup {
+                    table,
+                    process,
+                    cache: None,
+                    subscribers: Vec::new(),
+                };
+                self.groups.insert(
+                    group_id,
+                    GroupEntry {
+                        group: Box::new(group),
+                        key: subscriber.key,
+                        tokens,
+                    },
+                );
+                group_id
+            }
+        };
+
+        let id = subscriber.id;
+        let tokens = TimerTokens {
+            coalesce: self.new_token(group_id, Target::Subscriber(id, Trigger::Timer)),
+            retry: self.new_token(group_id, Target::Subscriber(id, Trigger::Retry)),
+        };
+        let state = State::new(
+            subscriber.options,
+            tokens,
+            Arc::clone(&subscriber.shared.snapshot),
+            subscriber.callback,
+        );
+        let entry = self.groups.get_mut(&group_id).unwrap();
+        let group: &mut TableGroup<M> = entry.group.as_any_mut().downcast_mut().unwrap();
+        group.subscribers.push(Subscriber {
+            id,
+            tokens,
+            state,
+            shared: subscriber.shared,
+        });
+        Ok(group_id)
+    }
+
+    fn unsubscribe(&mut self, id: u64) {
+        let found = self
+            .groups
+            .iter_mut()
+            .find_map(|(group_id, entry)| Some((*group_id, entry.group.unsubscribe(id)?)));
+        if let Some((group_id, tokens)) = found {
+            self.cleanup(group_id, vec![tokens]);
+        }
+    }
+
+    fn handle(&mut self, group_id: u64, target: Target) {
+        let Some(entry) = self.groups.get_mut(&group_id) else {
+            return;
+        };
+        let finished = entry.group.handle(target, &self.poll);
+        self.cleanup(group_id, finished);
+    }
+
+    /// Forgets the tokens of the subscribers that have finished, and removes the group
+    /// if it has no subscriber anymore.
+    fn cleanup(&mut self, group_id: u64, finished: Vec<TimerTokens>) {
+        for tokens in finished {
+            self.tokens.remove(&tokens.coalesce);
+            self.tokens.remove(&tokens.retry);
+        }
+        if !self.groups[&group_id].group.is_empty() {
+            return;
+        }
+        let entry = self.groups.remove(&group_id).unwrap();
+        entry.group.deregister(self.poll.registry());
+        for token in entry.tokens {
+            self.tokens.remove(&token);
+        }
+        if let Some(key) = entry.key {
+            self.shared_tables.remove(&key);
+        }
+    }
+}
+
+impl Drop for HubLoop {
+    fn drop(&mut self) {
+        // Wake the subscriptions up. If the hub has failed, they fail too.
+        for entry in self.groups.values_mut() {
+            entry.group.finish_all(self.stopped);
+        }
+    }
+}
+
+/// A mount table and its subscribers, whatever the type of mount entries.
+trait Group: Send {
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Handles an event, and returns the tokens of the subscribers that have finished.
+    fn handle(&mut self, target: Target, poll: &Poll) -> Vec<TimerTokens>;
+
+    /// Removes a subscriber, and returns its tokens if it was in the group.
+    fn unsubscribe(&mut self, id: u64) -> Option<TimerTokens>;
+
+    fn is_empty(&self) -> bool;
+
+    fn deregister(&self, registry: &Registry);
+
+    /// Finishes all the subscribers, because the hub has been stopped or has failed.
+    fn finish_all(&mut self, stopped: bool);
+}
+
+struct TableGroup<M: MountEntry> {
+    table: Box<dyn MountTableSource<M>>,
+    process: Option<WatchedProcess>,
+    /// The mounts that have been read since the last change, shared by the subscribers.
+    cache: Option<Vec<M>>,
+    subscribers: Vec<Subscriber<M>>,
+}
+
+struct Subscriber<M: MountEntry> {
+    id: u64,
+    tokens: TimerTokens,
+    state: State<M, BoxedCallback<M>>,
+    shared: Arc<Shared<M>>,
+}
+
+impl<M: MountEntry> TableGroup<M> {
+    /// Handles a trigger for the subscriber at `index`, and returns `false` if it has finished.
+    fn handle_subscriber(&mut self, index: usize, trigger: Trigger, poll: &Poll) -> bool {
+        let Self {
+            table,
+            cache,
+            subscribers,
+            ..
+        } = self;
+        let mut read = CachedRead { table, cache };
+        let subscriber = &mut subscribers[index];
+        match subscriber.state.handle_event(trigger, &mut read, poll) {
+            Ok(true) => true,
+            Ok(false) => {
+                subscriber.shared.finish(Ok(()));
+                false
+            }
+            Err(e) => {
+                log::error!("error in subscriber {}: {e:?}", subscriber.id);
+                subscriber.shared.finish(Err(WatchError(e)));
+                false
+            }
+        }
+    }
+
+    fn remove(&mut self, index: usize) -> TimerTokens {
+        self.subscribers.swap_remove(index).tokens
+    }
+}
+
+/// Reads the table of a group once per change, for all its subscribers.
+struct CachedRead<'a, M> {
+    table: &'a mut Box<dyn MountTableSource<M>>,
+    cache: &'a mut Option<Vec<M>>,
+}
+
+impl<M: MountEntry> ReadMounts<M> for CachedRead<'_, M> {
+    fn read(&mut self) -> Result<Vec<M>, ReadError> {
+        match self.cache {
+            Some(mounts) => Ok(mounts.clone()),
+            None => {
+                let mounts = self.table.read()?;
+                *self.cache = Some(mounts.clone());
+                Ok(mounts)
+            }
+        }
+    }
+
+    fn read_changes(&mut self) -> Result<Option<TableChanges<M>>, ReadError> {
+        // A source that knows its changes is never shared, it has a single subscriber.
+        self.table.read_changes()
+    }
+}
+
+impl<M: MountEntry> Group for TableGroup<M> {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn handle(&mut self, target: Target, poll: &Poll) -> Vec<TimerTokens> {
+        let mut finished = Vec::new();
+        match target {
+            Target::Table => {
+                // the table has changed, the next read will get the new mounts
+                self.cache = None;
+                let mut i = 0;
+                while i < self.subscribers.len() {
+                    if self.handle_subscriber(i, Trigger::Mount, poll) {
+                        i += 1;
+                    } else {
+                        finished.push(self.remove(i));
+                    }
+                }
+            }
+            Target::Process => {
+                let pid = self.process.as_ref().map_or(0, |p| p.pid);
+                for subscriber in self.subscribers.drain(..) {
+                    let error = WatchError(ErrorImpl::ProcessExited(pid));
+                    subscriber.shared.finish(Err(error));
+                    finished.push(subscriber.tokens);
+                }
+            }
+            Target::Subscriber(id, trigger) => {
+                if let Some(i) = self.subscribers.iter().position(|s| s.id == id) {
+                    if !self.handle_subscriber(i, trigger, poll) {
+                        finished.push(self.remove(i));
+                    }
+                }
+            }
+        }
+        finished
+    }
+
+    fn unsubscribe(&mut self, id: u64) -> Option<TimerTokens> {
+        let i = self.subscribers.iter().position(|s| s.id == id)?;
+        self.subscribers[i].shared.finish(Ok(()));
+        Some(self.remove(i))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    fn deregister(&self, registry: &Registry) {
+        // The table may be shared with the user (e.g. a FakeMountTable): closing it
+        // is not enough to deregister it.
+        let fd = self.table.readiness_fd().as_raw_fd();
+        if let Err(e) = registry.deregister(&mut SourceFd(&fd)) {
+            log::warn!("failed to deregister a mount table: {e}");
+        }
+    }
+
+    fn finish_all(&mut self, stopped: bool) {
+        for subscriber in self.subscribers.drain(..) {
+            let result = match stopped {
+                true => Ok(()),
+                false => Err(WatchError(ErrorImpl::HubStopped)),
+            };
+            subscriber.shared.finish(result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::table_key;
+    use crate::{builder::TableLocation, mount::LinuxMount, mountinfo::MountInfo};
+
+    #[test]
+    fn same_namespace_same_key() {
+        let key = |ns: File| {
+            let location = Some(TableLocation::Namespace(ns.into()));
+            table_key::<LinuxMount>(false, location).unwrap().0.unwrap()
+        };
+        let a = key(File::open("/proc/self/ns/mnt").unwrap());
+        let b = key(File::open("/proc/self/ns/mnt").unwrap());
+        assert_eq!(a, b);
+
+        let info = table_key::<MountInfo>(false, None).unwrap().0.unwrap();
+        let default = table_key::<LinuxMount>(false, None).unwrap().0.unwrap();
+        assert_ne!(info, default);
+        assert!(table_key::<LinuxMount>(true, None).unwrap().0.is_none());
+    }
+}
@@ -14,6 +14,7 @@
 //!     } else {
 //!         println!("new mounts: {:?}", event.mounted);
 //!         println!("removed mounts: {:?}", event.unmounted);
+//!         println!("modified mounts: {:?}", event.changed);
 //!     }
 //!     WatchControl::Continue
 //! });
@@ -23,12 +24,99 @@
 //! # Advanced features
 //!
 //! For more advanced use cases, have a look at [`WatchControl::Coalesce`] and [`callback::coalesce`].
+//!
+//! To only get notified about some filesystems, use [`MountWatcher::with_filter`].
+//! The watcher can be further configured with [`MountWatcher::builder`].
+//! To get the mounts that the watcher knows about, without waiting for an event,
+//! use [`MountWatcher::snapshot`].
+//!
+//! To get more details about each mount, such as its ID and its parent, use
+//! [`MountWatcher::new_mountinfo`], which watches `/proc/self/mountinfo` instead of `/proc/mounts`.
+//! With these details, [`tree::MountTree`] organizes the mounts as a tree.
+//! To check the mount options, such as `ro` or `size=`, use [`mount::MountEntry::options`].
+//! [`MountChange::options_diff`] lists the options that have changed during a remount.
+//!
+//! If the background thread fails, for instance because the mount table cannot be read,
+//! the error is returned by [`MountWatcher::join`]. See [`watch::ErrorPolicy`] to retry or skip
+//! the failed reads instead. To use `?` in your callback, use [`MountWatcher::try_new`].
+//!
+//! # Paths
+//!
+//! To get the filesystem that contains a file, use [`mount::find_mount_for_path`].
+//! To only get notified when this filesystem changes, use [`MountWatcherBuilder::backing_mount_of`].
+//!
+//! # fstab
+//!
+//! To check that the mounted filesystems match `/etc/fstab`, use [`fstab::drift`].
+//! To get notified when `/etc/fstab` is modified, use [`MountWatcherBuilder::watch_fstab`].
+//! To find the device designated by `UUID=...` or `/dev/sda1`, use [`device::DeviceResolver`].
+//!
+//! # Containers
+//!
+//! To watch the mounts of a container, give the pid of one of its processes to
+//! [`MountWatcherBuilder::pid`], or its mount namespace to [`MountWatcherBuilder::mount_namespace`].
+//!
+//! To run many callbacks on a single thread, for instance one per container,
+//! subscribe them to a [`hub::MountWatchHub`].
+//!
+//! # Testing
+//!
+//! To test your code without mounting anything, give a [`table::FakeMountTable`]
+//! to [`MountWatcherBuilder::source`].
+//!
+//! # Backends
+//!
+//! On recent kernels, [`MountWatcherBuilder::statmount`] gets the mounts with the
+//! `listmount` and `statmount` syscalls instead of parsing `/proc/mounts`.
+//! With [`MountWatcherBuilder::fanotify`], the kernel reports each attached or detached
+//! mount, and the events are built without reading the whole table again, but the remounts
+//! are not reported.
+//!
+//! # Waiting for a mount
+//!
+//! To block until a filesystem is mounted or unmounted, use [`wait_for_mount`] and [`wait_for_unmount`].
+//!
+//! # Channel
+//!
+//! To handle the events on your own thread, without a callback, use [`MountWatcher::channel`].
+//!
+//! # mio
+//!
+//! To watch the mounts from your own [`mio`] event loop, without a background thread,
+//! use [`source::MountSource`].
+//!
+//! # Async
+//!
+//! With the `tokio` feature, [`MountWatcher::stream`] returns a [`Stream`](futures_core::Stream)
+//! of events, driven by the tokio runtime instead of a background thread.
 
+pub mod builder;
 pub mod callback;
+pub mod channel;
+pub mod device;
+pub mod fanotify;
+pub mod filter;
+pub mod fstab;
+pub mod hub;
 pub mod mount;
+pub mod mountinfo;
+pub mod options;
+pub mod source;
+pub mod statmount;
+#[cfg(feature = "tokio")]
+pub mod stream;
+pub mod table;
+#[cfg(test)]
+mod test_util;
+pub mod tree;
+pub mod wait;
 pub mod watch;
 
-pub use watch::{MountEvent, MountWatcher, WatchControl};
+pub use builder::MountWatcherBuilder;
+pub use wait::{wait_for_mount, wait_for_unmount};
+#[cfg(feature = "tokio")]
+pub use wait::{wait_for_mount_async, wait_for_unmount_async};
+pub use watch::{MountChange, MountEvent, MountSnapshot, MountWatcher, WatchControl};
 
 #[cfg(not(target_os = "linux"))]
 compile_error!("only Linux is supported");
@@ -1,61 +1,230 @@
 //! Parse /proc/mounts.
 
 use std::{
+    borrow::Cow,
+    ffi::{OsStr, OsString},
+    fmt::Debug,
     fs::File,
+    hash::Hash,
     io::{Read, Seek},
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
 use thiserror::Error;
 
+use crate::{
+    mountinfo::{list_current_mountinfo, MountInfo},
+    options::MountOptions,
+};
+
 pub const PROC_MOUNTS_PATH: &str = "/proc/mounts";
 
 /// A mounted filesystem.
 ///
 /// See `man fstab` for a detailed description of the fields.
+///
+/// The kernel escapes some characters (space, tab, newline and backslash) of `spec`
+/// and `mount_point` with octal sequences, for instance `/media/My Disk` is written
+/// `/media/My\040Disk`. These fields are decoded, but the raw form is kept in
+/// `raw_spec` and `raw_mount_point`.
+///
+/// Paths are not required to be valid UTF-8, hence the use of [`OsString`] and [`PathBuf`].
+/// The mount options, however, are converted to UTF-8 in a lossy way.
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct LinuxMount {
-    pub spec: String,
-    pub mount_point: String,
+    pub spec: OsString,
+    pub mount_point: PathBuf,
     pub fs_type: String,
     pub mount_options: Vec<String>,
     pub dump_fs_freq: u32,
     pub fsck_fs_passno: u32,
+    /// `spec`, as written in the mount table (with escape sequences).
+    pub raw_spec: OsString,
+    /// `mount_point`, as written in the mount table (with escape sequences).
+    pub raw_mount_point: OsString,
 }
 
-/// Error while parsing `/proc/mounts`.
+/// An entry of a mount table, such as a line of `/proc/mounts`.
+///
+/// The [`MountWatcher`](crate::MountWatcher) can watch any mount table whose entries implement this trait,
+/// see [`LinuxMount`] and [`MountInfo`].
+pub trait MountEntry: Debug + Clone + Eq + Hash + Send + Sync + 'static {
+    /// Path of the file that contains the mount table.
+    const TABLE_PATH: &'static str;
+
+    /// Parses one line of the mount table.
+    fn parse_line(line: &[u8]) -> Result<Self, ParseError>;
+
+    /// Returns `true` if `self` and `other` describe the same mount, possibly in two
+    /// different states (e.g. before and after a remount with different options).
+    fn is_same_mount(&self, other: &Self) -> bool;
+
+    /// Source of the mount, e.g. a device.
+    fn spec(&self) -> &OsStr;
+
+    /// Mount point.
+    fn mount_point(&self) -> &Path;
+
+    /// Type of the filesystem.
+    fn fs_type(&self) -> &str;
+
+    /// Mount options.
+    fn mount_options(&self) -> &[String];
+
+    /// Mount options, with typed accessors.
+    fn options(&self) -> MountOptions<'_> {
+        MountOptions::new(self.mount_options())
+    }
+
+    /// Returns the mount that contains `path`, among the `mounts` of a table.
+    ///
+    /// `path` must be absolute and canonical. By default, this is the mount with the longest
+    /// mount point that is a prefix of `path`, and the last one if several filesystems are
+    /// mounted on top of each other. This is wrong if a parent directory has been overmounted
+    /// after the mount, which [`MountInfo`] can detect.
+    fn find_for_path<'a>(mounts: &'a [Self], path: &Path) -> Option<&'a Self> {
+        mounts
+            .iter()
+            .filter(|m| path.starts_with(m.mount_point()))
+            .max_by_key(|m| m.mount_point().as_os_str().len())
+    }
+
+    /// Like [`find_for_path`](Self::find_for_path), with the `infos` of the same mounts
+    /// in the mountinfo format, which describe how they are stacked.
+    ///
+    /// By default, the `infos` are ignored. [`LinuxMount`] uses them to take the overmounted
+    /// filesystems into account, see [`find_mount_for_path`].
+    fn find_for_path_with_mountinfo<'a>(
+        mounts: &'a [Self],
+        infos: &[MountInfo],
+        path: &Path,
+    ) -> Option<&'a Self> {
+        let _ = infos;
+        Self::find_for_path(mounts, path)
+    }
+}
+
+/// Error while parsing a mount table.
 #[derive(Debug, Error)]
-#[error("invalid mount line: {input}")]
+#[error("invalid mount line ({reason}): {input}")]
 pub struct ParseError {
     pub(crate) input: String,
+    pub(crate) reason: ParseErrorReason,
 }
 
-/// Error while reading/parsing `/proc/mounts`.
+/// Why a line could not be parsed.
+#[derive(Debug, Error)]
+pub(crate) enum ParseErrorReason {
+    #[error("missing or invalid field")]
+    InvalidField,
+    #[error("invalid escape sequence in {field} at byte {offset}")]
+    InvalidEscape { field: &'static str, offset: usize },
+    #[error("{field} is not valid UTF-8")]
+    InvalidUtf8 { field: &'static str },
+}
+
+impl ParseError {
+    fn new(line: &[u8], reason: ParseErrorReason) -> Self {
+        Self {
+            input: String::from_utf8_lossy(line).into_owned(),
+            reason,
+        }
+    }
+}
+
+/// Error while reading/parsing a mount table.
 #[derive(Debug, Error)]
 pub enum ReadError {
-    #[error("failed to parse {PROC_MOUNTS_PATH}")]
+    #[error("failed to parse the mount table")]
     Parse(#[from] ParseError),
-    #[error("failed to read {PROC_MOUNTS_PATH}")]
+    #[error("failed to read the mount table")]
     Io(#[from] std::io::Error),
 }
 
+impl MountEntry for LinuxMount {
+    const TABLE_PATH: &'static str = PROC_MOUNTS_PATH;
+
+    fn parse_line(line: &[u8]) -> Result<Self, ParseError> {
+        Self::try_parse(line)
+    }
+
+    /// Two lines of `/proc/mounts` describe the same mount if they have the same
+    /// mount point and the same source.
+    fn is_same_mount(&self, other: &Self) -> bool {
+        self.mount_point == other.mount_point && self.spec == other.spec
+    }
+
+    fn spec(&self) -> &OsStr {
+        &self.spec
+    }
+
+    fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+
+    fn fs_type(&self) -> &str {
+        &self.fs_type
+    }
+
+    fn mount_options(&self) -> &[String] {
+        &self.mount_options
+    }
+
+    /// Finds the mount in the `infos`, then the line of `mounts` that describes it.
+    fn find_for_path_with_mountinfo<'a>(
+        mounts: &'a [Self],
+        infos: &[MountInfo],
+        path: &Path,
+    ) -> Option<&'a Self> {
+        let Some(info) = MountInfo::find_for_path(infos, path) else {
+            return Self::find_for_path(mounts, path);
+        };
+
+        // Both tables list the mounts in the same order: if several mounts look identical,
+        // take the one at the same rank.
+        let same = |mount_point: &Path, spec: &OsStr, fs_type: &str| {
+            mount_point == info.mount_point && spec == info.spec && fs_type == info.fs_type
+        };
+        let rank = infos
+            .iter()
+            .take_while(|i| !std::ptr::eq(*i, info))
+            .filter(|i| same(&i.mount_point, &i.spec, &i.fs_type))
+            .count();
+        mounts
+            .iter()
+            .filter(|m| same(&m.mount_point, &m.spec, &m.fs_type))
+            .nth(rank)
+            // the tables have changed between the two reads
+            .or_else(|| Self::find_for_path(mounts, path))
+    }
+}
+
 impl LinuxMount {
     /// Attempts to parse one line of `/proc/mounts`.
     /// Returns `None` if it fails.
     pub fn parse(line: &str) -> Option<Self> {
-        let mut fields = line.split_ascii_whitespace().into_iter();
-        let spec = fields.next()?.to_string();
-        let mount_point = fields.next()?.to_string();
-        let fs_type = fields.next()?.to_string();
-        let mount_options = fields.next()?.split(',').map(ToOwned::to_owned).collect();
-        let dump_fs_freq = fields.next()?.parse().ok()?;
-        let fsck_fs_passno = fields.next()?.parse().ok()?;
-        Some(Self {
-            spec,
-            mount_point,
-            fs_type,
-            mount_options,
-            dump_fs_freq,
-            fsck_fs_passno,
+        Self::try_parse(line.as_bytes()).ok()
+    }
+
+    /// Parses one line of `/proc/mounts`.
+    ///
+    /// Unlike [`parse`](Self::parse), this accepts non-UTF-8 input and returns an error
+    /// that explains what went wrong.
+    pub fn try_parse(line: &[u8]) -> Result<Self, ParseError> {
+        let mut fields = Fields::new(line);
+        let raw_spec = fields.next_raw()?;
+        let raw_mount_point = fields.next_raw()?;
+        Ok(Self {
+            spec: fields.unescape(raw_spec, "spec")?,
+            mount_point: fields.unescape(raw_mount_point, "mount point")?.into(),
+            fs_type: fields.next_str("filesystem type")?,
+            mount_options: fields.next_list()?,
+            dump_fs_freq: fields.next_number()?,
+            fsck_fs_passno: fields.next_number()?,
+            raw_spec: OsString::from_vec(raw_spec.to_vec()),
+            raw_mount_point: OsString::from_vec(raw_mount_point.to_vec()),
         })
     }
 }
@@ -63,44 +232,256 @@ impl LinuxMount {
 /// Returns the filesystems that are currently mounted.
 pub fn list_current_mounts() -> Result<Vec<LinuxMount>, ReadError> {
     let mut file = File::open(PROC_MOUNTS_PATH)?;
-    read_proc_mounts(&mut file)
+    read_mount_table(&mut file, OnInvalidLine::Fail)
 }
 
-/// Reads `/proc/mounts` from the beginning and parses its content.
-pub(crate) fn read_proc_mounts(file: &mut File) -> Result<Vec<LinuxMount>, ReadError> {
-    let mut content = String::with_capacity(4096);
+/// Returns the mounted filesystem that contains `path`.
+///
+/// The path is canonicalized first, hence it must exist. The mount is found in
+/// `/proc/self/mountinfo`, to take the overmounted filesystems into account, see
+/// [`find_mountinfo_for_path`](crate::mountinfo::find_mountinfo_for_path).
+///
+/// Returns `None` if no mount contains the path, which should not happen.
+pub fn find_mount_for_path(path: impl AsRef<Path>) -> Result<Option<LinuxMount>, ReadError> {
+    let path = path.as_ref().canonicalize()?;
+    let infos = list_current_mountinfo()?;
+    let mounts = list_current_mounts()?;
+    Ok(LinuxMount::find_for_path_with_mountinfo(&mounts, &infos, &path).cloned())
+}
+
+/// What to do when a line of a mount table cannot be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OnInvalidLine {
+    /// Return an error.
+    Fail,
+    /// Log a warning and ignore the line.
+    Skip,
+}
+
+/// Reads a mount table from the beginning and parses its content.
+pub(crate) fn read_mount_table<M: MountEntry>(
+    file: &mut File,
+    on_invalid: OnInvalidLine,
+) -> Result<Vec<M>, ReadError> {
+    let mut content = Vec::with_capacity(4096);
     file.rewind()?;
-    file.read_to_string(&mut content)?;
+    file.read_to_end(&mut content)?;
     let mut mounts = Vec::with_capacity(64);
-    parse_proc_mounts(&content, &mut mounts)?;
+    parse_table(&content, &mut mounts, on_invalid)?;
     Ok(mounts)
 }
 
-/// Parses the content of `/proc/mounts` and stores the result in `buf`.
-pub(crate) fn parse_proc_mounts(
-    content: &str,
-    buf: &mut Vec<LinuxMount>,
+/// Parses each non-empty, non-comment line of `content` and stores the result in `buf`.
+pub(crate) fn parse_table<M: MountEntry>(
+    content: &[u8],
+    buf: &mut Vec<M>,
+    on_invalid: OnInvalidLine,
 ) -> Result<(), ParseError> {
-    for line in content.lines() {
-        let line = line.trim_start_matches(|c: char| c.is_ascii_whitespace());
-        if !line.is_empty() && !line.starts_with('#') {
-            let m = LinuxMount::parse(line).ok_or_else(|| ParseError {
-                input: line.to_owned(),
-            })?;
-            buf.push(m);
+    parse_lines(content, buf, on_invalid, M::TABLE_PATH, M::parse_line)
+}
+
+/// Like [`parse_table`], with a custom parser for the lines of the file `table_path`.
+pub(crate) fn parse_lines<T>(
+    content: &[u8],
+    buf: &mut Vec<T>,
+    on_invalid: OnInvalidLine,
+    table_path: &str,
+    parse_line: impl Fn(&[u8]) -> Result<T, ParseError>,
+) -> Result<(), ParseError> {
+    for line in content.split(|b| *b == b'\n') {
+        let start = line
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(line.len());
+        let line = &line[start..];
+        if !line.is_empty() && !line.starts_with(b"#") {
+            match parse_line(line) {
+                Ok(m) => buf.push(m),
+                Err(e) if on_invalid == OnInvalidLine::Skip => {
+                    log::warn!("skipping invalid line of {table_path}: {e}");
+                }
+                Err(e) => return Err(e),
+            }
         }
     }
     Ok(())
 }
 
+/// Iterator over the whitespace-separated fields of a line, with parsing helpers.
+pub(crate) struct Fields<'a> {
+    line: &'a [u8],
+    fields: std::slice::Split<'a, u8, fn(&u8) -> bool>,
+}
+
+impl<'a> Fields<'a> {
+    pub(crate) fn new(line: &'a [u8]) -> Self {
+        Self {
+            line,
+            fields: line.split(u8::is_ascii_whitespace as fn(&u8) -> bool),
+        }
+    }
+
+    fn invalid(&self) -> ParseError {
+        ParseError::new(self.line, ParseErrorReason::InvalidField)
+    }
+
+    /// Returns the next field, without any conversion.
+    pub(crate) fn next_raw(&mut self) -> Result<&'a [u8], ParseError> {
+        loop {
+            match self.fields.next() {
+                Some([]) => continue, // consecutive spaces
+                Some(field) => return Ok(field),
+                None => return Err(self.invalid()),
+            }
+        }
+    }
+
+    /// Returns the next field, which must be valid UTF-8.
+    pub(crate) fn next_str(&mut self, field_name: &'static str) -> Result<String, ParseError> {
+        let field = self.next_raw()?;
+        String::from_utf8(field.to_vec()).map_err(|_| {
+            ParseError::new(
+                self.line,
+                ParseErrorReason::InvalidUtf8 { field: field_name },
+            )
+        })
+    }
+
+    /// Parses the next field as a number.
+    pub(crate) fn next_number<T: FromStr>(&mut self) -> Result<T, ParseError> {
+        let field = self.next_raw()?;
+        std::str::from_utf8(field)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| self.invalid())
+    }
+
+    /// Parses the next field as a number, if there is one.
+    pub(crate) fn next_optional_number<T: FromStr>(&mut self) -> Result<Option<T>, ParseError> {
+        if self.fields.clone().all(|f| f.is_empty()) {
+            return Ok(None);
+        }
+        self.next_number().map(Some)
+    }
+
+    /// Parses the next field as two numbers separated by `sep`, such as `major:minor`.
+    pub(crate) fn next_pair<T: FromStr>(&mut self, sep: u8) -> Result<(T, T), ParseError> {
+        let field = self.next_raw()?;
+        let parse = |s: &[u8]| std::str::from_utf8(s).ok()?.parse().ok();
+        let mut parts = field.splitn(2, |b| *b == sep);
+        match (parts.next().and_then(parse), parts.next().and_then(parse)) {
+            (Some(a), Some(b)) => Ok((a, b)),
+            _ => Err(self.invalid()),
+        }
+    }
+
+    /// Parses the next field as a comma-separated list, such as mount options.
+    ///
+    /// Each element is converted to UTF-8 in a lossy way.
+    pub(crate) fn next_list(&mut self) -> Result<Vec<String>, ParseError> {
+        let field = self.next_raw()?;
+        Ok(field
+            .split(|b| *b == b',')
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .collect())
+    }
+
+    /// Returns the next field, with its octal escape sequences decoded.
+    pub(crate) fn next_unescaped(
+        &mut self,
+        field_name: &'static str,
+    ) -> Result<OsString, ParseError> {
+        let field = self.next_raw()?;
+        self.unescape(field, field_name)
+    }
+
+    /// Decodes the octal escape sequences (`\ooo`) of a field.
+    ///
+    /// `field` must be a slice of the line, so that the error can indicate
+    /// where the invalid sequence is in the line.
+    pub(crate) fn unescape(
+        &self,
+        field: &[u8],
+        field_name: &'static str,
+    ) -> Result<OsString, ParseError> {
+        let bytes = unescape_octal(field).map_err(|offset_in_field| {
+            let offset = field.as_ptr() as usize - self.line.as_ptr() as usize + offset_in_field;
+            ParseError::new(
+                self.line,
+                ParseErrorReason::InvalidEscape {
+                    field: field_name,
+                    offset,
+                },
+            )
+        })?;
+        Ok(OsString::from_vec(bytes.into_owned()))
+    }
+}
+
+/// Decodes the octal escape sequences (`\ooo`) that the kernel uses in mount tables.
+///
+/// On error, returns the offset of the invalid sequence.
+pub(crate) fn unescape_octal(input: &[u8]) -> Result<Cow<'_, [u8]>, usize> {
+    if !input.contains(&b'\\') {
+        return Ok(Cow::Borrowed(input));
+    }
+    let mut res = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'\\' {
+            let digits = input
+                .get(i + 1..i + 4)
+                .filter(|digits| digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+                .ok_or(i)?;
+            let value = digits
+                .iter()
+                .fold(0u16, |acc, d| acc * 8 + u16::from(d - b'0'));
+            res.push(u8::try_from(value).map_err(|_| i)?);
+            i += 4;
+        } else {
+            res.push(input[i]);
+            i += 1;
+        }
+    }
+    Ok(Cow::Owned(res))
+}
+
+/// Encodes the characters that the kernel escapes in mount tables (space, tab, newline
+/// and backslash) with octal sequences. This is the reverse of [`unescape_octal`].
+pub(crate) fn escape_octal(input: &[u8]) -> Cow<'_, [u8]> {
+    let must_escape = |c: &u8| matches!(c, b' ' | b'\t' | b'\n' | b'\\');
+    if !input.iter().any(must_escape) {
+        return Cow::Borrowed(input);
+    }
+    let mut res = Vec::with_capacity(input.len() + 6);
+    for c in input {
+        if must_escape(c) {
+            res.extend_from_slice(format!("\\{c:03o}").as_bytes());
+        } else {
+            res.push(*c);
+        }
+    }
+    Cow::Owned(res)
+}
+
 #[cfg(test)]
 mod tests {
     use pretty_assertions::assert_eq;
 
-    use super::{parse_proc_mounts, LinuxMount};
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt, path::PathBuf};
+
+    use super::{
+        escape_octal, find_mount_for_path, parse_table, unescape_octal, LinuxMount, MountEntry,
+        OnInvalidLine, ParseError,
+    };
+    use crate::mountinfo::MountInfo;
 
     fn vec_str(values: &[&str]) -> Vec<String> {
-        values.into_iter().map(|s| s.to_string()).collect()
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn parse_proc_mounts(content: &str, buf: &mut Vec<LinuxMount>) -> Result<(), ParseError> {
+        parse_table(content.as_bytes(), buf, OnInvalidLine::Fail)
     }
 
     #[test]
@@ -111,20 +492,22 @@ tmpfs /run tmpfs rw,nosuid,nodev,noexec,relatime,size=1599352k,mode=755,inode64
 cgroup2 /sys/fs/cgroup cgroup2 rw,nosuid,nodev,noexec,relatime,nsdelegate,memory_recursiveprot 0 0
 /dev/nvme0n1p1 /boot/efi vfat rw,relatime,errors=remount-ro 0 0";
         let mut mounts = Vec::new();
-        parse_proc_mounts(&content, &mut mounts).unwrap();
+        parse_proc_mounts(content, &mut mounts).unwrap();
 
         let expected = vec![
             LinuxMount {
-                spec: String::from("sysfs"),
-                mount_point: String::from("/sys"),
+                spec: OsString::from("sysfs"),
+                mount_point: PathBuf::from("/sys"),
                 fs_type: String::from("sysfs"),
                 mount_options: vec_str(&["rw", "nosuid", "nodev", "noexec", "relatime"]),
                 dump_fs_freq: 0,
                 fsck_fs_passno: 0,
+                raw_spec: OsString::from("sysfs"),
+                raw_mount_point: OsString::from("/sys"),
             },
             LinuxMount {
-                spec: String::from("tmpfs"),
-                mount_point: String::from("/run"),
+                spec: OsString::from("tmpfs"),
+                mount_point: PathBuf::from("/run"),
                 fs_type: String::from("tmpfs"),
                 mount_options: vec_str(&[
                     "rw",
@@ -138,10 +521,12 @@ cgroup2 /sys/fs/cgroup cgroup2 rw,nosuid,nodev,noexec,relatime,nsdelegate,memory
                 ]),
                 dump_fs_freq: 1,
                 fsck_fs_passno: 2,
+                raw_spec: OsString::from("tmpfs"),
+                raw_mount_point: OsString::from("/run"),
             },
             LinuxMount {
-                spec: String::from("cgroup2"),
-                mount_point: String::from("/sys/fs/cgroup"),
+                spec: OsString::from("cgroup2"),
+                mount_point: PathBuf::from("/sys/fs/cgroup"),
                 fs_type: String::from("cgroup2"),
                 mount_options: vec_str(&[
                     "rw",
@@ -154,14 +539,18 @@ cgroup2 /sys/fs/cgroup cgroup2 rw,nosuid,nodev,noexec,relatime,nsdelegate,memory
                 ]),
                 dump_fs_freq: 0,
                 fsck_fs_passno: 0,
+                raw_spec: OsString::from("cgroup2"),
+                raw_mount_point: OsString::from("/sys/fs/cgroup"),
             },
             LinuxMount {
-                spec: String::from("/dev/nvme0n1p1"),
-                mount_point: String::from("/boot/efi"),
+                spec: OsString::from("/dev/nvme0n1p1"),
+                mount_point: PathBuf::from("/boot/efi"),
                 fs_type: String::from("vfat"),
                 mount_options: vec_str(&["rw", "relatime", "errors=remount-ro"]),
                 dump_fs_freq: 0,
                 fsck_fs_passno: 0,
+                raw_spec: OsString::from("/dev/nvme0n1p1"),
+                raw_mount_point: OsString::from("/boot/efi"),
             },
         ];
         assert_eq!(expected, mounts);
@@ -174,9 +563,130 @@ cgroup2 /sys/fs/cgroup cgroup2 rw,nosuid,nodev,noexec,relatime,nsdelegate,memory
         parse_proc_mounts("croup2 /sys/fs/cgroup", &mut mounts).unwrap_err();
     }
 
+    #[test]
+    fn parsing_escapes() {
+        let content = r"/dev/sdb1 /media/My\040Disk\011\134 vfat rw 0 0";
+        let mut mounts = Vec::new();
+        parse_proc_mounts(content, &mut mounts).unwrap();
+        let expected = vec![LinuxMount {
+            spec: OsString::from("/dev/sdb1"),
+            mount_point: PathBuf::from("/media/My Disk\t\\"),
+            fs_type: String::from("vfat"),
+            mount_options: vec_str(&["rw"]),
+            dump_fs_freq: 0,
+            fsck_fs_passno: 0,
+            raw_spec: OsString::from("/dev/sdb1"),
+            raw_mount_point: OsString::from(r"/media/My\040Disk\011\134"),
+        }];
+        assert_eq!(expected, mounts);
+    }
+
+    #[test]
+    fn parsing_invalid_escapes() {
+        let mut mounts = Vec::new();
+        let err =
+            parse_proc_mounts(r"/dev/sdb1 /media/My\04 vfat rw 0 0", &mut mounts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r"invalid mount line (invalid escape sequence in mount point at byte 19): /dev/sdb1 /media/My\04 vfat rw 0 0"
+        );
+        parse_proc_mounts(r"/dev/sdb1 /media/My\400 vfat rw 0 0", &mut mounts).unwrap_err();
+        parse_proc_mounts(r"/dev/sdb1 /media/My\ vfat rw 0 0", &mut mounts).unwrap_err();
+        parse_proc_mounts(r"/dev/sd\b1 /media vfat rw 0 0", &mut mounts).unwrap_err();
+    }
+
+    #[test]
+    fn escaping() {
+        let raw = b"/media/My Disk\\Tab\t";
+        let escaped = escape_octal(raw);
+        assert_eq!(&*escaped, br"/media/My\040Disk\134Tab\011".as_slice());
+        assert_eq!(&*unescape_octal(&escaped).unwrap(), raw.as_slice());
+        assert_eq!(&*escape_octal(b"/mnt/usb"), b"/mnt/usb".as_slice());
+    }
+
+    #[test]
+    fn parsing_non_utf8() {
+        let content = b"/dev/sdc1 /media/caf\xe9\\040\xff vfat rw 0 0";
+        let mut mounts = Vec::new();
+        parse_table(content, &mut mounts, OnInvalidLine::Fail).unwrap();
+        let expected = vec![LinuxMount {
+            spec: OsString::from("/dev/sdc1"),
+            mount_point: PathBuf::from(OsString::from_vec(b"/media/caf\xe9 \xff".to_vec())),
+            fs_type: String::from("vfat"),
+            mount_options: vec_str(&["rw"]),
+            dump_fs_freq: 0,
+            fsck_fs_passno: 0,
+            raw_spec: OsString::from("/dev/sdc1"),
+            raw_mount_point: OsString::from_vec(b"/media/caf\xe9\\040\xff".to_vec()),
+        }];
+        assert_eq!(expected, mounts);
+    }
+
+    #[test]
+    fn parsing_skip_invalid() {
+        let content = b"tmpfs /run tmpfs rw 0 0\nbadbad\nsysfs /sys sysfs rw 0 0";
+        let mut mounts = Vec::new();
+        parse_table::<LinuxMount>(content, &mut mounts, OnInvalidLine::Fail).unwrap_err();
+        mounts.clear();
+        parse_table::<LinuxMount>(content, &mut mounts, OnInvalidLine::Skip).unwrap();
+        let mount_points: Vec<_> = mounts.iter().map(|m| m.mount_point.as_path()).collect();
+        assert_eq!(mount_points, vec!["/run", "/sys"]);
+    }
+
     #[test]
     fn parsing_comments() {
         let mut mounts = Vec::new();
         parse_proc_mounts("\n# badbad\n", &mut mounts).unwrap();
     }
+
+    #[test]
+    fn find_for_path() {
+        let content = "
+/dev/sda1 / ext4 rw 0 0
+a /a tmpfs rw 0 0
+b /a/b tmpfs rw 0 0
+a2 /a tmpfs rw 0 0
+c /c tmpfs rw 0 0";
+        let mut mounts = Vec::new();
+        parse_proc_mounts(content, &mut mounts).unwrap();
+        let find = |path: &str| {
+            LinuxMount::find_for_path(&mounts, &PathBuf::from(path))
+                .map(|m| m.spec.clone())
+                .unwrap()
+        };
+        assert_eq!(find("/"), "/dev/sda1");
+        assert_eq!(find("/a/file"), "a2");
+        assert_eq!(find("/a/b/file"), "b");
+        assert_eq!(find("/c/file"), "c");
+        assert_eq!(find("/cc"), "/dev/sda1");
+
+        // /a/b is mounted, then /a is overmounted
+        let infos = "
+1 0 8:1 / / rw - ext4 /dev/sda1 rw
+2 1 0:20 / /a rw - tmpfs a rw
+3 2 0:21 / /a/b rw - tmpfs b rw
+4 2 0:22 / /a rw - tmpfs a2 rw
+5 1 0:23 / /c rw - tmpfs c rw";
+        let mut infos_list = Vec::new();
+        parse_table::<MountInfo>(infos.as_bytes(), &mut infos_list, OnInvalidLine::Fail).unwrap();
+        let find = |path: &str| {
+            LinuxMount::find_for_path_with_mountinfo(&mounts, &infos_list, &PathBuf::from(path))
+                .map(|m| m.spec.clone())
+                .unwrap()
+        };
+        assert_eq!(find("/a/b/file"), "a2");
+        assert_eq!(find("/c/file"), "c");
+        assert_eq!(find("/"), "/dev/sda1");
+        // without mountinfo
+        let find = |path: &str| {
+            LinuxMount::find_for_path_with_mountinfo(&mounts, &[], &PathBuf::from(path))
+                .map(|m| m.spec.clone())
+                .unwrap()
+        };
+        assert_eq!(find("/a/b/file"), "b");
+
+        let proc = find_mount_for_path("/proc/self").unwrap().unwrap();
+        assert_eq!(proc.fs_type, "proc");
+        find_mount_for_path("/does/not/exist").unwrap_err();
+    }
 }
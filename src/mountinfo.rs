@@ -0,0 +1,345 @@
+//! Parse /proc/self/mountinfo.
+//!
+//! Compared to `/proc/mounts`, `mountinfo` contains more information about each mount,
+//! such as its unique ID, the ID of its parent and its propagation tags.
+//! In particular, the mount ID allows to distinguish two mounts that look identical
+//! in `/proc/mounts`, like two bind mounts of the same directory at the same place.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    mount::{read_mount_table, Fields, MountEntry, OnInvalidLine, ParseError, ReadError},
+    options::MountOptions,
+};
+
+pub const PROC_MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
+/// A mount, as described in `/proc/self/mountinfo`.
+///
+/// See `man proc_pid_mountinfo` for a detailed description of the fields.
+///
+/// Like in [`LinuxMount`](crate::mount::LinuxMount), the octal escape sequences
+/// of `root`, `mount_point` and `spec` are decoded.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct MountInfo {
+    /// Unique ID of the mount (may be reused after an unmount).
+    pub mount_id: u32,
+    /// ID of the parent mount, or of self for the root of the mount tree.
+    pub parent_id: u32,
+    /// Major ID of the device (`st_dev`) of the files in this filesystem.
+    pub major: u32,
+    /// Minor ID of the device (`st_dev`) of the files in this filesystem.
+    pub minor: u32,
+    /// Pathname of the directory in the filesystem which forms the root of this mount.
+    pub root: PathBuf,
+    /// Pathname of the mount point, relative to the process's root directory.
+    pub mount_point: PathBuf,
+    /// Per-mount options.
+    pub mount_options: Vec<String>,
+    /// Propagation tags (optional fields).
+    pub propagation: Vec<PropagationTag>,
+    /// Type of the filesystem.
+    pub fs_type: String,
+    /// Filesystem-specific information, or `"none"`.
+    ///
+    /// This is the same as [`LinuxMount::spec`](crate::mount::LinuxMount::spec).
+    pub spec: OsString,
+    /// Per-superblock options.
+    pub super_options: Vec<String>,
+}
+
+/// Optional field of a mountinfo line, which describes how mount events propagate.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum PropagationTag {
+    /// `shared:X`: the mount is shared in peer group X.
+    Shared(u32),
+    /// `master:X`: the mount is a slave to shared peer group X.
+    Master(u32),
+    /// `propagate_from:X`: the mount is a slave and receives propagation from shared peer group X.
+    PropagateFrom(u32),
+    /// `unbindable`: the mount is unbindable.
+    Unbindable,
+    /// A tag that this library does not know about.
+    Unknown(String),
+}
+
+impl PropagationTag {
+    fn parse(field: &str) -> Self {
+        let parse_group = |s: &str, f: fn(u32) -> Self| match s.parse() {
+            Ok(group) => f(group),
+            Err(_) => Self::Unknown(field.to_owned()),
+        };
+        match field.split_once(':') {
+            Some(("shared", group)) => parse_group(group, Self::Shared),
+            Some(("master", group)) => parse_group(group, Self::Master),
+            Some(("propagate_from", group)) => parse_group(group, Self::PropagateFrom),
+            _ if field == "unbindable" => Self::Unbindable,
+            _ => Self::Unknown(field.to_owned()),
+        }
+    }
+}
+
+impl MountEntry for MountInfo {
+    const TABLE_PATH: &'static str = PROC_MOUNTINFO_PATH;
+
+    fn parse_line(line: &[u8]) -> Result<Self, ParseError> {
+        Self::try_parse(line)
+    }
+
+    /// Two mountinfo entries describe the same mount if they have the same mount ID,
+    /// device, root and mount point.
+    ///
+    /// The kernel reuses the IDs of the unmounted filesystems: the ID alone could match
+    /// a new mount with an old one.
+    fn is_same_mount(&self, other: &Self) -> bool {
+        self.mount_id == other.mount_id
+            && (self.major, self.minor) == (other.major, other.minor)
+            && self.root == other.root
+            && self.mount_point == other.mount_point
+    }
+
+    fn spec(&self) -> &OsStr {
+        &self.spec
+    }
+
+    fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+
+    fn fs_type(&self) -> &str {
+        &self.fs_type
+    }
+
+    /// Returns the per-mount options (not the superblock options).
+    fn mount_options(&self) -> &[String] {
+        &self.mount_options
+    }
+
+    /// Walks down the mount tree, from the root to `path`, with the parent IDs.
+    ///
+    /// Unlike the default implementation, this does not depend on the order of the mounts.
+    fn find_for_path<'a>(mounts: &'a [Self], path: &Path) -> Option<&'a Self> {
+        let is_root = |m: &MountInfo| {
+            m.parent_id == m.mount_id || !mounts.iter().any(|p| p.mount_id == m.parent_id)
+        };
+        let mut current = mounts
+            .iter()
+            .rev()
+            .find(|m| is_root(m) && path.starts_with(&m.mount_point))?;
+        loop {
+            // If two children of `current` contain `path`, like `/a` and `/a/b`, the second one
+            // has been mounted first (otherwise its parent would be `/a`), and is hidden.
+            let next = mounts
+                .iter()
+                .rev()
+                .filter(|m| {
+                    m.parent_id == current.mount_id
+                        && m.mount_id != current.mount_id
+                        && path.starts_with(&m.mount_point)
+                })
+                .min_by_key(|m| m.mount_point.as_os_str().len());
+            match next {
+                Some(next) => current = next,
+                None => return Some(current),
+            }
+        }
+    }
+}
+
+impl MountInfo {
+    /// Per-superblock options, with typed accessors.
+    ///
+    /// The per-mount options are given by [`MountEntry::options`].
+    pub fn super_block_options(&self) -> MountOptions<'_> {
+        MountOptions::new(&self.super_options)
+    }
+
+    /// Attempts to parse one line of `/proc/self/mountinfo`.
+    /// Returns `None` if it fails.
+    pub fn parse(line: &str) -> Option<Self> {
+        Self::try_parse(line.as_bytes()).ok()
+    }
+
+    /// Parses one line of `/proc/self/mountinfo`.
+    ///
+    /// Unlike [`parse`](Self::parse), this accepts non-UTF-8 input and returns an error
+    /// that explains what went wrong.
+    pub fn try_parse(line: &[u8]) -> Result<Self, ParseError> {
+        let mut fields = Fields::new(line);
+        let mount_id = fields.next_number()?;
+        let parent_id = fields.next_number()?;
+        let (major, minor) = fields.next_pair(b':')?;
+        let root = fields.next_unescaped("root")?.into();
+        let mount_point = fields.next_unescaped("mount point")?.into();
+        let mount_options = fields.next_list()?;
+
+        // zero or more optional fields, terminated by a single hyphen
+        let mut propagation = Vec::new();
+        loop {
+            match fields.next_raw()? {
+                b"-" => break,
+                field => propagation.push(PropagationTag::parse(&String::from_utf8_lossy(field))),
+            }
+        }
+
+        Ok(Self {
+            mount_id,
+            parent_id,
+            major,
+            minor,
+            root,
+            mount_point,
+            mount_options,
+            propagation,
+            fs_type: fields.next_str("filesystem type")?,
+            spec: fields.next_unescaped("spec")?,
+            super_options: fields.next_list()?,
+        })
+    }
+}
+
+/// Returns the mounts that are currently visible to this process, with their mountinfo.
+pub fn list_current_mountinfo() -> Result<Vec<MountInfo>, ReadError> {
+    let mut file = File::open(PROC_MOUNTINFO_PATH)?;
+    read_mount_table(&mut file, OnInvalidLine::Fail)
+}
+
+/// Returns the mount that contains `path`.
+///
+/// Like [`find_mount_for_path`](crate::mount::find_mount_for_path), with the details of
+/// `/proc/self/mountinfo`.
+pub fn find_mountinfo_for_path(path: impl AsRef<Path>) -> Result<Option<MountInfo>, ReadError> {
+    let path = path.as_ref().canonicalize()?;
+    let mounts = list_current_mountinfo()?;
+    Ok(MountInfo::find_for_path(&mounts, &path).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use std::{ffi::OsString, path::PathBuf};
+
+    use super::{find_mountinfo_for_path, MountInfo, PropagationTag};
+    use crate::mount::{parse_table, MountEntry, OnInvalidLine, ParseError};
+
+    fn vec_str(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn parse_proc_mountinfo(content: &str, buf: &mut Vec<MountInfo>) -> Result<(), ParseError> {
+        parse_table(content.as_bytes(), buf, OnInvalidLine::Fail)
+    }
+
+    #[test]
+    fn parsing() {
+        let content = "
+23 28 0:22 / /proc rw,relatime shared:12 - proc proc rw
+36 35 98:0 /mnt1 /mnt\\0402 rw,noatime master:1 propagate_from:2 - ext3 /dev/root rw,errors=continue
+29 28 254:16 / /mnt/data ro,nosuid,nodev,relatime - ext4 /dev/vdb ro";
+        let mut mounts = Vec::new();
+        parse_proc_mountinfo(content, &mut mounts).unwrap();
+
+        let expected = vec![
+            MountInfo {
+                mount_id: 23,
+                parent_id: 28,
+                major: 0,
+                minor: 22,
+                root: PathBuf::from("/"),
+                mount_point: PathBuf::from("/proc"),
+                mount_options: vec_str(&["rw", "relatime"]),
+                propagation: vec![PropagationTag::Shared(12)],
+                fs_type: String::from("proc"),
+                spec: OsString::from("proc"),
+                super_options: vec_str(&["rw"]),
+            },
+            MountInfo {
+                mount_id: 36,
+                parent_id: 35,
+                major: 98,
+                minor: 0,
+                root: PathBuf::from("/mnt1"),
+                mount_point: PathBuf::from("/mnt 2"),
+                mount_options: vec_str(&["rw", "noatime"]),
+                propagation: vec![PropagationTag::Master(1), PropagationTag::PropagateFrom(2)],
+                fs_type: String::from("ext3"),
+                spec: OsString::from("/dev/root"),
+                super_options: vec_str(&["rw", "errors=continue"]),
+            },
+            MountInfo {
+                mount_id: 29,
+                parent_id: 28,
+                major: 254,
+                minor: 16,
+                root: PathBuf::from("/"),
+                mount_point: PathBuf::from("/mnt/data"),
+                mount_options: vec_str(&["ro", "nosuid", "nodev", "relatime"]),
+                propagation: vec![],
+                fs_type: String::from("ext4"),
+                spec: OsString::from("/dev/vdb"),
+                super_options: vec_str(&["ro"]),
+            },
+        ];
+        assert_eq!(expected, mounts);
+    }
+
+    #[test]
+    fn parsing_propagation() {
+        assert_eq!(
+            PropagationTag::parse("unbindable"),
+            PropagationTag::Unbindable
+        );
+        assert_eq!(
+            PropagationTag::parse("shared:abc"),
+            PropagationTag::Unknown(String::from("shared:abc"))
+        );
+        assert_eq!(
+            PropagationTag::parse("future:1"),
+            PropagationTag::Unknown(String::from("future:1"))
+        );
+    }
+
+    #[test]
+    fn parsing_error() {
+        let mut mounts = Vec::new();
+        parse_proc_mountinfo("badbad", &mut mounts).unwrap_err();
+        parse_proc_mountinfo("23 28 0:22 / /proc rw,relatime", &mut mounts).unwrap_err();
+        parse_proc_mountinfo("23 28 0:22 / /proc rw,relatime - proc", &mut mounts).unwrap_err();
+        parse_proc_mountinfo("23 28 022 / /proc rw - proc proc rw", &mut mounts).unwrap_err();
+        parse_proc_mountinfo(r"23 28 0:22 / /a\9 rw - proc proc rw", &mut mounts).unwrap_err();
+    }
+
+    #[test]
+    fn find_for_path() {
+        // /a/b is mounted, then /a is overmounted, /c/d is mounted, then /c hides it
+        let content = "
+1 0 8:1 / / rw - ext4 /dev/sda1 rw
+2 1 0:20 / /a rw - tmpfs a rw
+3 2 0:21 / /a/b rw - tmpfs b rw
+4 2 0:22 / /a rw - tmpfs a2 rw
+6 1 0:24 / /c/d rw - tmpfs d rw
+5 1 0:23 / /c rw - tmpfs c rw";
+        let mut mounts = Vec::new();
+        parse_proc_mountinfo(content, &mut mounts).unwrap();
+        let find = |path: &str| {
+            MountInfo::find_for_path(&mounts, &PathBuf::from(path))
+                .map(|m| m.mount_id)
+                .unwrap()
+        };
+        assert_eq!(find("/"), 1);
+        assert_eq!(find("/etc/fstab"), 1);
+        assert_eq!(find("/a"), 4);
+        assert_eq!(find("/a/b/file"), 4);
+        assert_eq!(find("/ab"), 1);
+        assert_eq!(find("/c/d/file"), 5);
+        assert_eq!(find("/c/e"), 5);
+
+        let proc = find_mountinfo_for_path("/proc/self").unwrap().unwrap();
+        assert_eq!(proc.fs_type, "proc");
+    }
+}
@@ -0,0 +1,312 @@
+//! Structured access to the mount options.
+//!
+//! The mount tables give the options as a list of strings, such as
+//! `rw,nosuid,relatime,size=1024k`. [`MountOptions`] reads this list without copying it:
+//!
+//! ```
+//! use mount_watcher::{mount::MountEntry, mount::LinuxMount};
+//!
+//! let mount = LinuxMount::try_parse(b"tmpfs /tmp tmpfs rw,nosuid,nodev,size=1024k 0 0").unwrap();
+//! let options = mount.options();
+//! assert!(!options.read_only());
+//! assert!(options.nosuid());
+//! assert_eq!(options.get("size"), Some("1024k"));
+//! ```
+//!
+//! To list the options that have changed during a remount, see [`MountOptions::diff`].
+
+use std::{collections::HashSet, fmt};
+
+/// The options of a mount, borrowed from a list such as [`LinuxMount::mount_options`].
+///
+/// If an option is given several times, the last one wins.
+///
+/// [`LinuxMount::mount_options`]: crate::mount::LinuxMount::mount_options
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MountOptions<'a> {
+    options: &'a [String],
+}
+
+/// One mount option: a flag such as `nosuid`, or a pair such as `size=1024k`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MountOption<'a> {
+    pub key: &'a str,
+    pub value: Option<&'a str>,
+}
+
+/// How the access times are updated, see `man mount`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AtimeMode {
+    /// Only update the access time if it is older than the modification time (`relatime`).
+    Relatime,
+    /// Never update the access time (`noatime`).
+    Noatime,
+    /// Always update the access time (`strictatime`, or no option).
+    Strictatime,
+}
+
+/// The differences between two sets of options, see [`MountOptions::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptionsDiff<'a> {
+    /// The options that are only in the new set.
+    pub added: Vec<MountOption<'a>>,
+    /// The options that are only in the old set.
+    pub removed: Vec<MountOption<'a>>,
+    /// The options whose value has changed, e.g. `size=1m` to `size=2m`.
+    pub changed: Vec<OptionChange<'a>>,
+}
+
+/// An option whose value has changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OptionChange<'a> {
+    pub key: &'a str,
+    pub old: Option<&'a str>,
+    pub new: Option<&'a str>,
+}
+
+impl<'a> MountOptions<'a> {
+    pub fn new(options: &'a [String]) -> Self {
+        Self { options }
+    }
+
+    /// Iterates over the options, in their original order.
+    pub fn iter(&self) -> impl Iterator<Item = MountOption<'a>> + 'a {
+        self.options.iter().map(|o| MountOption::parse(o))
+    }
+
+    /// Returns the value of a `key=value` option.
+    ///
+    /// Returns `None` if the option is missing or has no value.
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.find(key).and_then(|o| o.value)
+    }
+
+    /// Returns `true` if the option is present, with or without a value.
+    pub fn contains(&self, key: &str) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Returns `true` if the mount is read-only (`ro`).
+    pub fn read_only(&self) -> bool {
+        self.iter()
+            .filter(|o| o.key == "ro" || o.key == "rw")
+            .last()
+            .is_some_and(|o| o.key == "ro")
+    }
+
+    /// Returns `true` if the set-user-ID and set-group-ID bits are ignored (`nosuid`).
+    pub fn nosuid(&self) -> bool {
+        self.contains("nosuid")
+    }
+
+    /// Returns `true` if the device files are not interpreted (`nodev`).
+    pub fn nodev(&self) -> bool {
+        self.contains("nodev")
+    }
+
+    /// Returns `true` if the programs cannot be executed (`noexec`).
+    pub fn noexec(&self) -> bool {
+        self.contains("noexec")
+    }
+
+    /// Returns `true` if the access times of the directories are not updated (`nodiratime`).
+    pub fn nodiratime(&self) -> bool {
+        self.contains("nodiratime")
+    }
+
+    /// Returns how the access times are updated.
+    pub fn atime(&self) -> AtimeMode {
+        self.iter()
+            .filter_map(|o| match o.key {
+                "relatime" => Some(AtimeMode::Relatime),
+                "noatime" => Some(AtimeMode::Noatime),
+                "strictatime" => Some(AtimeMode::Strictatime),
+                _ => None,
+            })
+            .last()
+            .unwrap_or(AtimeMode::Strictatime)
+    }
+
+    /// Lists the options that differ between `self` (the old options) and `new`.
+    ///
+    /// ```
+    /// use mount_watcher::options::{MountOption, MountOptions, OptionChange};
+    ///
+    /// let old = ["rw".to_owned(), "size=1m".to_owned()];
+    /// let new = ["ro".to_owned(), "size=2m".to_owned()];
+    /// let diff = MountOptions::new(&old).diff(&MountOptions::new(&new));
+    /// assert_eq!(diff.added, [MountOption { key: "ro", value: None }]);
+    /// assert_eq!(diff.removed, [MountOption { key: "rw", value: None }]);
+    /// assert_eq!(diff.changed, [OptionChange { key: "size", old: Some("1m"), new: Some("2m") }]);
+    /// ```
+    pub fn diff(&self, new: &MountOptions<'a>) -> OptionsDiff<'a> {
+        let mut diff = OptionsDiff::default();
+        for key in self.keys() {
+            let old_value = self.find(key).and_then(|o| o.value);
+            match new.find(key) {
+                None => diff.removed.push(MountOption {
+                    key,
+                    value: old_value,
+                }),
+                Some(o) if o.value != old_value => diff.changed.push(OptionChange {
+                    key,
+                    old: old_value,
+                    new: o.value,
+                }),
+                Some(_) => (),
+            }
+        }
+        for key in new.keys() {
+            if !self.contains(key) {
+                diff.added.push(MountOption {
+                    key,
+                    value: new.get(key),
+                });
+            }
+        }
+        diff
+    }
+
+    /// Iterates over the keys, without duplicates.
+    fn keys(&self) -> impl Iterator<Item = &'a str> + 'a {
+        let mut seen = HashSet::new();
+        self.iter()
+            .map(|o| o.key)
+            .filter(move |key| seen.insert(*key))
+    }
+
+    fn find(&self, key: &str) -> Option<MountOption<'a>> {
+        self.iter().filter(|o| o.key == key).last()
+    }
+}
+
+impl fmt::Display for MountOptions<'_> {
+    /// Writes the options separated by commas, as in the mount table.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.options.join(","))
+    }
+}
+
+impl<'a> MountOption<'a> {
+    fn parse(option: &'a str) -> Self {
+        match option.split_once('=') {
+            Some((key, value)) => Self {
+                key,
+                value: Some(value),
+            },
+            None => Self {
+                key: option,
+                value: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for MountOption<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            Some(value) => write!(f, "{}={value}", self.key),
+            None => f.write_str(self.key),
+        }
+    }
+}
+
+impl OptionsDiff<'_> {
+    /// Returns `true` if the two sets of options are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{AtimeMode, MountOption, MountOptions, OptionChange, OptionsDiff};
+
+    fn vec_str(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn accessors() {
+        let options = vec_str(&[
+            "rw",
+            "nosuid",
+            "nodev",
+            "noatime",
+            "size=1024k",
+            "mode=755",
+            "inode64",
+        ]);
+        let options = MountOptions::new(&options);
+        assert!(!options.read_only());
+        assert!(options.nosuid());
+        assert!(options.nodev());
+        assert!(!options.noexec());
+        assert!(!options.nodiratime());
+        assert_eq!(options.atime(), AtimeMode::Noatime);
+        assert_eq!(options.get("size"), Some("1024k"));
+        assert_eq!(options.get("mode"), Some("755"));
+        assert_eq!(options.get("inode64"), None);
+        assert!(options.contains("inode64"));
+        assert!(!options.contains("uid"));
+        assert_eq!(
+            options.iter().map(|o| o.key).collect::<Vec<_>>(),
+            ["rw", "nosuid", "nodev", "noatime", "size", "mode", "inode64"]
+        );
+        assert_eq!(
+            options.to_string(),
+            "rw,nosuid,nodev,noatime,size=1024k,mode=755,inode64"
+        );
+
+        let options = vec_str(&["rw", "ro", "errors=remount-ro", "data=ordered=x"]);
+        let options = MountOptions::new(&options);
+        assert!(options.read_only());
+        assert_eq!(options.atime(), AtimeMode::Strictatime);
+        assert_eq!(options.get("data"), Some("ordered=x"));
+        assert_eq!(MountOptions::new(&[]).atime(), AtimeMode::Strictatime);
+    }
+
+    #[test]
+    fn diff() {
+        let old = vec_str(&["rw", "nosuid", "relatime", "size=1m", "mode=755"]);
+        let new = vec_str(&["ro", "nosuid", "relatime", "size=2m", "mode", "noexec"]);
+        let diff = MountOptions::new(&old).diff(&MountOptions::new(&new));
+        assert_eq!(
+            diff,
+            OptionsDiff {
+                added: vec![
+                    MountOption {
+                        key: "ro",
+                        value: None
+                    },
+                    MountOption {
+                        key: "noexec",
+                        value: None
+                    }
+                ],
+                removed: vec![MountOption {
+                    key: "rw",
+                    value: None
+                }],
+                changed: vec![
+                    OptionChange {
+                        key: "size",
+                        old: Some("1m"),
+                        new: Some("2m")
+                    },
+                    OptionChange {
+                        key: "mode",
+                        old: Some("755"),
+                        new: None
+                    }
+                ],
+            }
+        );
+        assert!(!diff.is_empty());
+
+        let same = MountOptions::new(&old).diff(&MountOptions::new(&old));
+        assert!(same.is_empty());
+    }
+}
@@ -0,0 +1,265 @@
+//! Integration into your own [`mio`] event loop.
+//!
+//! Instead of spawning a background thread, you can register a [`MountSource`] to your
+//! [`Poll`](mio::Poll) and call [`MountSource::process`] when it is ready.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mount_watcher::source::MountSource;
+//!
+//! const MOUNTS: Token = Token(0);
+//! const COALESCE: Token = Token(1);
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut source = MountSource::new().unwrap();
+//! poll.registry().register(&mut source, MOUNTS, Interest::PRIORITY).unwrap();
+//! poll.registry().register(source.coalesce_timer(), COALESCE, Interest::READABLE).unwrap();
+//!
+//! let mut events = Events::with_capacity(8);
+//! loop {
+//!     // get the initial event, then the changes
+//!     if let Some(event) = source.process().unwrap() {
+//!         println!("new mounts: {:?}", event.mounted);
+//!     }
+//!     poll.poll(&mut events, None).unwrap();
+//! }
+//! ```
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io,
+    os::fd::{AsRawFd, RawFd},
+    path::Path,
+    time::Duration,
+};
+
+use mio::{event::Source, unix::SourceFd, Interest, Registry, Token};
+use timerfd::{SetTimeFlags, TimerFd, TimerState};
+
+use crate::{
+    mount::{read_mount_table, LinuxMount, MountEntry, OnInvalidLine, ReadError},
+    mountinfo::MountInfo,
+    watch::{diff_mounts, ErrorImpl, SetupError},
+    MountEvent,
+};
+
+/// Mount table that can be registered to a [`mio::Poll`].
+///
+/// Register it with [`Interest::PRIORITY`]: like in `MountWatcher`, a mount or an unmount
+/// is a PRIORITY event. To use [`coalesce`](Self::coalesce), register the
+/// [`coalesce_timer`](Self::coalesce_timer) too, with [`Interest::READABLE`].
+///
+/// When one of them is ready, call [`process`](Self::process) to get the changes.
+pub struct MountSource<M = LinuxMount> {
+    file: File,
+    timer: CoalesceTimer,
+    known_mounts: HashSet<M>,
+    /// The mounts of the last event, which are saved on the next call to `process` (unless we coalesce).
+    pending_mounts: Option<HashSet<M>>,
+    coalescing: bool,
+    initial: bool,
+    /// Number of times that `known_mounts` has been updated.
+    generation: u64,
+}
+
+/// Timer used by [`MountSource::coalesce`].
+///
+/// It becomes readable when the coalescing delay has elapsed.
+pub struct CoalesceTimer(TimerFd);
+
+impl MountSource {
+    /// Opens `/proc/mounts`.
+    pub fn new() -> Result<Self, SetupError> {
+        Self::with_path(LinuxMount::TABLE_PATH)
+    }
+
+    /// Opens `/proc/self/mountinfo`.
+    ///
+    /// See [`MountWatcher::new_mountinfo`](crate::MountWatcher::new_mountinfo).
+    pub fn new_mountinfo() -> Result<MountSource<MountInfo>, SetupError> {
+        MountSource::with_path(MountInfo::TABLE_PATH)
+    }
+}
+
+impl<M: MountEntry> MountSource<M> {
+    /// Opens the mount table at `path`, which must support `epoll` like `/proc/mounts`.
+    pub fn with_path(path: impl AsRef<Path>) -> Result<Self, SetupError> {
+        let file =
+            File::open(path).map_err(|e| SetupError(ErrorImpl::MountRead(ReadError::Io(e))))?;
+        let timer = TimerFd::new().map_err(|e| SetupError(ErrorImpl::TimerInit(e)))?;
+        Ok(Self {
+            file,
+            timer: CoalesceTimer(timer),
+            known_mounts: HashSet::with_capacity(8),
+            pending_mounts: None,
+            coalescing: false,
+            initial: true,
+            generation: 0,
+        })
+    }
+
+    /// Returns the timer to register in order to use [`coalesce`](Self::coalesce).
+    pub fn coalesce_timer(&mut self) -> &mut CoalesceTimer {
+        &mut self.timer
+    }
+
+    /// Reads the mount table and returns the changes, without blocking.
+    ///
+    /// The first call always returns the initial event, which contains the current mounts.
+    /// After that, `process` returns `None` if nothing has changed, or if the coalescing
+    /// delay has not elapsed yet.
+    pub fn process(&mut self) -> Result<Option<MountEvent<M>>, ReadError> {
+        // The previous event has been handled: save its mounts, unless we coalesce.
+        if let Some(mounts) = self.pending_mounts.take() {
+            if !self.coalescing {
+                self.known_mounts = mounts;
+                self.generation += 1;
+            }
+        }
+
+        let coalesced = self.coalescing;
+        if coalesced && self.timer.0.get_state() != TimerState::Disarmed {
+            // Ignore the changes until the deadline, we'll read them all at once.
+            return Ok(None);
+        }
+
+        // Skip the invalid lines: one odd entry should not stop the watcher.
+        let mounts = read_mount_table(&mut self.file, OnInvalidLine::Skip)?;
+        let mounts = HashSet::from_iter(mounts);
+        let initial = std::mem::take(&mut self.initial);
+        self.coalescing = false;
+        let Some(mut event) = diff_mounts(&self.known_mounts, &mounts, coalesced, initial) else {
+            return Ok(None);
+        };
+        event.generation = self.generation + 1;
+        self.pending_mounts = Some(mounts);
+        Ok(Some(event))
+    }
+
+    /// Coalesces the last event with the changes that will occur during the given delay.
+    ///
+    /// After the delay, the [`coalesce_timer`](Self::coalesce_timer) becomes ready and
+    /// [`process`](Self::process) returns a new event that includes the changes of the
+    /// last event, in addition to the new ones.
+    /// This is the equivalent of returning [`WatchControl::Coalesce`](crate::WatchControl::Coalesce)
+    /// from a callback.
+    ///
+    /// This must be called after receiving an event and before calling `process` again,
+    /// otherwise it has no effect.
+    pub fn coalesce(&mut self, delay: Duration) {
+        if self.pending_mounts.is_none() {
+            log::warn!("coalesce() called while no event is pending, ignoring it");
+            return;
+        }
+        log::trace!("start coalescing for {delay:?}");
+        self.timer
+            .0
+            .set_state(TimerState::Oneshot(delay), SetTimeFlags::Default);
+        self.coalescing = true;
+    }
+}
+
+impl<M> Source for MountSource<M> {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.file.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.file.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.file.as_raw_fd()).deregister(registry)
+    }
+}
+
+impl<M> AsRawFd for MountSource<M> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl Source for CoalesceTimer {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.0.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.0.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.0.as_raw_fd()).deregister(registry)
+    }
+}
+
+impl AsRawFd for CoalesceTimer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, time::Duration};
+
+    use mio::{Events, Interest, Poll, Token};
+    use pretty_assertions::assert_eq;
+
+    use super::MountSource;
+    use crate::mount::list_current_mounts;
+
+    #[test]
+    fn coalesce_initial_event() {
+        let mut poll = Poll::new().unwrap();
+        let mut source = MountSource::new().unwrap();
+        poll.registry()
+            .register(&mut source, Token(0), Interest::PRIORITY)
+            .unwrap();
+        poll.registry()
+            .register(source.coalesce_timer(), Token(1), Interest::READABLE)
+            .unwrap();
+
+        let event = source.process().unwrap().unwrap();
+        assert!(event.initial);
+        assert_eq!(event.generation, 1);
+        source.coalesce(Duration::from_millis(10));
+        assert!(source.process().unwrap().is_none());
+
+        let mut events = Events::with_capacity(8);
+        poll.poll(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+        assert!(events.iter().any(|e| e.token() == Token(1)));
+
+        let event = source.process().unwrap().unwrap();
+        assert!(event.coalesced);
+        assert!(!event.initial);
+        assert_eq!(event.generation, 1);
+        let expected: HashSet<_> = list_current_mounts().unwrap().into_iter().collect();
+        let actual: HashSet<_> = event.mounted.into_iter().collect();
+        assert_eq!(expected, actual);
+    }
+}
@@ -0,0 +1,594 @@
+//! Mount table based on the `listmount(2)` and `statmount(2)` syscalls.
+//!
+//! Instead of reading and parsing the text of `/proc/mounts`, [`StatmountTable`] asks the
+//! kernel for the list of mount IDs, then for the attributes of each mount.
+//! The mount table file is still used to get notified of the changes.
+//!
+//! These syscalls are only available in recent kernels. [`MountWatcherBuilder::statmount`]
+//! uses them if possible, and falls back to the mount table file otherwise.
+//!
+//! [`MountWatcherBuilder::statmount`]: crate::MountWatcherBuilder::statmount
+
+use std::{
+    ffi::OsString,
+    fs::File,
+    io,
+    marker::PhantomData,
+    os::{
+        fd::{AsFd, BorrowedFd},
+        unix::ffi::OsStringExt,
+    },
+    path::PathBuf,
+};
+
+use mio::Interest;
+
+use crate::{
+    mount::{escape_octal, LinuxMount, MountEntry, ReadError},
+    mountinfo::{MountInfo, PropagationTag},
+    table::MountTableSource,
+};
+
+// The syscall numbers are the same on all the architectures that use the generic table.
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    target_arch = "riscv64",
+    target_arch = "loongarch64",
+    target_arch = "powerpc",
+    target_arch = "powerpc64",
+    target_arch = "s390x"
+))]
+mod sys {
+    pub const SYS_STATMOUNT: libc::c_long = 457;
+    pub const SYS_LISTMOUNT: libc::c_long = 458;
+}
+
+#[cfg(not(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    target_arch = "riscv64",
+    target_arch = "loongarch64",
+    target_arch = "powerpc",
+    target_arch = "powerpc64",
+    target_arch = "s390x"
+)))]
+mod sys {
+    // unknown syscall numbers: the syscalls will fail with ENOSYS, and we'll fall back to /proc
+    pub const SYS_STATMOUNT: libc::c_long = -1;
+    pub const SYS_LISTMOUNT: libc::c_long = -1;
+}
+
+/// Lists all the mounts of the current namespace.
+const LSMT_ROOT: u64 = u64::MAX;
+
+const STATMOUNT_SB_BASIC: u64 = 0x1;
+const STATMOUNT_MNT_BASIC: u64 = 0x2;
+const STATMOUNT_PROPAGATE_FROM: u64 = 0x4;
+const STATMOUNT_MNT_ROOT: u64 = 0x8;
+const STATMOUNT_MNT_POINT: u64 = 0x10;
+const STATMOUNT_FS_TYPE: u64 = 0x20;
+const STATMOUNT_MNT_OPTS: u64 = 0x80;
+const STATMOUNT_FS_SUBTYPE: u64 = 0x100;
+const STATMOUNT_SB_SOURCE: u64 = 0x200;
+const STATMOUNT_SUPPORTED_MASK: u64 = 0x1000;
+
+/// Everything we need to build a [`LinuxMount`] or a [`MountInfo`].
+const REQUESTED_MASK: u64 = STATMOUNT_SB_BASIC
+    | STATMOUNT_MNT_BASIC
+    | STATMOUNT_PROPAGATE_FROM
+    | STATMOUNT_MNT_ROOT
+    | STATMOUNT_MNT_POINT
+    | STATMOUNT_FS_TYPE
+    | STATMOUNT_MNT_OPTS
+    | STATMOUNT_FS_SUBTYPE
+    | STATMOUNT_SB_SOURCE;
+
+/// The attributes that every mount must have. The kernel omits the empty strings,
+/// and the mount point of the mounts that are outside of our root.
+const REQUIRED_MASK: u64 = STATMOUNT_SB_BASIC
+    | STATMOUNT_MNT_BASIC
+    | STATMOUNT_PROPAGATE_FROM
+    | STATMOUNT_MNT_ROOT
+    | STATMOUNT_MNT_POINT
+    | STATMOUNT_FS_TYPE;
+
+const MOUNT_ATTR_RDONLY: u64 = 0x1;
+const MOUNT_ATTR_NOSUID: u64 = 0x2;
+const MOUNT_ATTR_NODEV: u64 = 0x4;
+const MOUNT_ATTR_NOEXEC: u64 = 0x8;
+const MOUNT_ATTR__ATIME: u64 = 0x70;
+const MOUNT_ATTR_RELATIME: u64 = 0x0;
+const MOUNT_ATTR_NOATIME: u64 = 0x10;
+const MOUNT_ATTR_NODIRATIME: u64 = 0x80;
+const MOUNT_ATTR_IDMAP: u64 = 0x100000;
+const MOUNT_ATTR_NOSYMFOLLOW: u64 = 0x200000;
+
+const SB_RDONLY: u32 = 0x1;
+const SB_SYNCHRONOUS: u32 = 0x10;
+const SB_DIRSYNC: u32 = 0x80;
+const SB_LAZYTIME: u32 = 0x2000000;
+
+const MS_UNBINDABLE: u64 = 1 << 17;
+const MS_SLAVE: u64 = 1 << 19;
+const MS_SHARED: u64 = 1 << 20;
+
+/// Size of the fixed part of `struct statmount`, the strings come after it.
+const STATMOUNT_HEADER_SIZE: usize = 512;
+
+/// `struct mnt_id_req`, first version.
+#[repr(C)]
+struct MntIdReq {
+    size: u32,
+    spare: u32,
+    mnt_id: u64,
+    param: u64,
+}
+
+impl MntIdReq {
+    fn new(mnt_id: u64, param: u64) -> Self {
+        Self {
+            size: std::mem::size_of::<Self>() as u32,
+            spare: 0,
+            mnt_id,
+            param,
+        }
+    }
+}
+
+/// Mount table obtained with `listmount` and `statmount`.
+///
+/// The entries are the same as the ones of the mount table file, except that the
+/// security options (e.g. SELinux contexts) may appear in a different position.
+#[derive(Debug)]
+pub struct StatmountTable<M = LinuxMount> {
+    /// The mount table file, only used for the notifications.
+    file: File,
+    stats: MountStats,
+    entry: PhantomData<fn() -> M>,
+}
+
+impl<M: MountEntry> StatmountTable<M> {
+    /// Checks that the syscalls are supported by the kernel, and opens the mount table file
+    /// of `M` to get the notifications.
+    ///
+    /// Returns an error of kind [`Unsupported`](io::ErrorKind::Unsupported) if the kernel
+    /// is too old.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            file: File::open(M::TABLE_PATH)?,
+            stats: MountStats::new()?,
+            entry: PhantomData,
+        })
+    }
+
+    /// Returns `true` if the kernel supports everything that we need.
+    pub fn is_supported() -> bool {
+        Self::new().is_ok()
+    }
+}
+
+impl MountTableSource<LinuxMount> for StatmountTable<LinuxMount> {
+    fn read(&mut self) -> Result<Vec<LinuxMount>, ReadError> {
+        let mounts = self.stats.all()?;
+        Ok(mounts.into_iter().map(|(_, m)| m).collect())
+    }
+
+    fn readiness_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+
+    fn readiness_interest(&self) -> Interest {
+        Interest::PRIORITY
+    }
+}
+
+impl MountTableSource<MountInfo> for StatmountTable<MountInfo> {
+    fn read(&mut self) -> Result<Vec<MountInfo>, ReadError> {
+        let mounts = self.stats.all()?;
+        Ok(mounts.into_iter().map(|(_, m)| m).collect())
+    }
+
+    fn readiness_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+
+    fn readiness_interest(&self) -> Interest {
+        Interest::PRIORITY
+    }
+}
+
+/// Mount entry that can be built from the result of `statmount`.
+pub(crate) trait FromStatmount: Sized {
+    fn from_statmount(stat: &Statmount) -> Self;
+}
+
+impl FromStatmount for LinuxMount {
+    fn from_statmount(stat: &Statmount) -> Self {
+        stat.to_linux_mount()
+    }
+}
+
+impl FromStatmount for MountInfo {
+    fn from_statmount(stat: &Statmount) -> Self {
+        stat.to_mountinfo()
+    }
+}
+
+/// Gets the mounts with `listmount` and `statmount`, reusing the buffers.
+#[derive(Debug)]
+pub(crate) struct MountStats {
+    ids: Vec<u64>,
+    buf: Vec<u8>,
+}
+
+impl MountStats {
+    /// Checks that the syscalls are supported by the kernel.
+    pub(crate) fn new() -> io::Result<Self> {
+        let mut stats = Self {
+            ids: Vec::with_capacity(64),
+            buf: vec![0; 4096],
+        };
+        stats.check_support()?;
+        Ok(stats)
+    }
+
+    fn check_support(&mut self) -> io::Result<()> {
+        let unsupported = |e: io::Error| match e.raw_os_error() {
+            Some(libc::ENOSYS | libc::EINVAL) => io::Error::new(
+                io::ErrorKind::Unsupported,
+                "listmount/statmount not supported",
+            ),
+            _ => e,
+        };
+        self.list_mounts().map_err(unsupported)?;
+        let Some(&id) = self.ids.first() else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no mount found"));
+        };
+        self.stat_mount(id).map_err(unsupported)?;
+        // Older kernels ignore the flags that they don't know, check that they know ours.
+        let stat = Statmount { buf: &self.buf };
+        let supported = match stat.mask() & STATMOUNT_SUPPORTED_MASK {
+            0 => 0,
+            _ => stat.supported_mask(),
+        };
+        if supported & REQUESTED_MASK != REQUESTED_MASK {
+            log::debug!("statmount supports {supported:#x}, we need {REQUESTED_MASK:#x}");
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "statmount does not support all the required attributes",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Lists the IDs of all the mounts into `self.ids`.
+    fn list_mounts(&mut self) -> io::Result<()> {
+        self.ids.clear();
+        let mut chunk = [0u64; 256];
+        loop {
+            let last = self.ids.last().copied().unwrap_or(0);
+            let req = MntIdReq::new(LSMT_ROOT, last);
+            // SAFETY: the request and the buffer are valid for the duration of the call
+            let res = unsafe {
+                libc::syscall(
+                    sys::SYS_LISTMOUNT,
+                    &req as *const MntIdReq,
+                    chunk.as_mut_ptr(),
+                    chunk.len(),
+                    0,
+                )
+            };
+            if res < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let n = res as usize;
+            self.ids.extend_from_slice(&chunk[..n]);
+            if n < chunk.len() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Gets the attributes of a mount into `self.buf`, which is enlarged if needed.
+    fn stat_mount(&mut self, id: u64) -> io::Result<()> {
+        let req = MntIdReq::new(id, REQUESTED_MASK | STATMOUNT_SUPPORTED_MASK);
+        loop {
+            // SAFETY: the request and the buffer are valid for the duration of the call
+            let res = unsafe {
+                libc::syscall(
+                    sys::SYS_STATMOUNT,
+                    &req as *const MntIdReq,
+                    self.buf.as_mut_ptr(),
+                    self.buf.len(),
+                    0,
+                )
+            };
+            if res == 0 {
+                return Ok(());
+            }
+            let e = io::Error::last_os_error();
+            match e.raw_os_error() {
+                Some(libc::EOVERFLOW) => {
+                    // the strings don't fit in the buffer
+                    let len = self.buf.len() * 2;
+                    self.buf.resize(len, 0);
+                }
+                Some(libc::EAGAIN | libc::EINTR) => (),
+                _ => return Err(e),
+            }
+        }
+    }
+
+    /// Gets one mount by ID. Returns `None` if it has disappeared, or if it is not
+    /// visible in `/proc/mounts`.
+    pub(crate) fn get<M: FromStatmount>(&mut self, id: u64) -> io::Result<Option<M>> {
+        match self.stat_mount(id) {
+            Ok(()) => (),
+            Err(e) if e.raw_os_error() == Some(libc::ENOENT) => {
+                log::trace!("mount {id} has disappeared");
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+        let stat = Statmount { buf: &self.buf };
+        if stat.mask() & REQUIRED_MASK != REQUIRED_MASK {
+            // the mount point is outside of our root, /proc/mounts doesn't show it either
+            log::trace!("skipping mount {id}, incomplete mask {:#x}", stat.mask());
+            return Ok(None);
+        }
+        Ok(Some(M::from_statmount(&stat)))
+    }
+
+    /// Gets all the mounts, with their ID.
+    pub(crate) fn all<M: FromStatmount>(&mut self) -> io::Result<Vec<(u64, M)>> {
+        self.list_mounts()?;
+        let ids = std::mem::take(&mut self.ids);
+        let mut mounts = Vec::with_capacity(ids.len());
+        for &id in &ids {
+            match self.get(id) {
+                Ok(Some(mount)) => mounts.push((id, mount)),
+                Ok(None) => (),
+                Err(e) => {
+                    self.ids = ids;
+                    return Err(e);
+                }
+            }
+        }
+        self.ids = ids;
+        Ok(mounts)
+    }
+}
+
+/// View of a `struct statmount` filled by the kernel.
+pub(crate) struct Statmount<'a> {
+    buf: &'a [u8],
+}
+
+impl Statmount<'_> {
+    fn u32_at(&self, offset: usize) -> u32 {
+        u32::from_ne_bytes(self.buf[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(&self, offset: usize) -> u64 {
+        u64::from_ne_bytes(self.buf[offset..offset + 8].try_into().unwrap())
+    }
+
+    /// Returns the string whose offset is stored at `offset`, or an empty string if
+    /// `flag` is not in the mask.
+    fn str_at(&self, offset: usize, flag: u64) -> &[u8] {
+        if self.mask() & flag == 0 {
+            return &[];
+        }
+        let start = STATMOUNT_HEADER_SIZE + self.u32_at(offset) as usize;
+        let s = &self.buf[start..];
+        let end = s.iter().position(|b| *b == 0).unwrap_or(s.len());
+        &s[..end]
+    }
+
+    fn mask(&self) -> u64 {
+        self.u64_at(8)
+    }
+
+    fn supported_mask(&self) -> u64 {
+        self.u64_at(144)
+    }
+
+    fn sb_dev(&self) -> (u32, u32) {
+        (self.u32_at(16), self.u32_at(20))
+    }
+
+    fn sb_flags(&self) -> u32 {
+        self.u32_at(32)
+    }
+
+    fn fs_type(&self) -> String {
+        let fs_type = String::from_utf8_lossy(self.str_at(36, STATMOUNT_FS_TYPE));
+        match self.str_at(120, STATMOUNT_FS_SUBTYPE) {
+            [] => fs_type.into_owned(),
+            subtype => format!("{fs_type}.{}", String::from_utf8_lossy(subtype)),
+        }
+    }
+
+    fn mnt_id_old(&self) -> u32 {
+        self.u32_at(56)
+    }
+
+    fn mnt_parent_id_old(&self) -> u32 {
+        self.u32_at(60)
+    }
+
+    fn mnt_attr(&self) -> u64 {
+        self.u64_at(64)
+    }
+
+    fn mnt_propagation(&self) -> u64 {
+        self.u64_at(72)
+    }
+
+    fn mnt_peer_group(&self) -> u64 {
+        self.u64_at(80)
+    }
+
+    fn mnt_master(&self) -> u64 {
+        self.u64_at(88)
+    }
+
+    fn propagate_from(&self) -> u64 {
+        self.u64_at(96)
+    }
+
+    fn mnt_root(&self) -> PathBuf {
+        PathBuf::from(OsString::from_vec(
+            self.str_at(104, STATMOUNT_MNT_ROOT).to_vec(),
+        ))
+    }
+
+    fn mnt_point(&self) -> &[u8] {
+        self.str_at(108, STATMOUNT_MNT_POINT)
+    }
+
+    fn sb_source(&self) -> &[u8] {
+        self.str_at(124, STATMOUNT_SB_SOURCE)
+    }
+
+    /// Filesystem-specific options.
+    fn fs_options(&self) -> impl Iterator<Item = String> + '_ {
+        self.str_at(4, STATMOUNT_MNT_OPTS)
+            .split(|b| *b == b',')
+            .filter(|opt| !opt.is_empty())
+            .map(|opt| String::from_utf8_lossy(opt).into_owned())
+    }
+
+    /// Per-mount options, like in mountinfo, without `ro` or `rw`.
+    fn mount_flags(&self, options: &mut Vec<String>) {
+        let attr = self.mnt_attr();
+        let flags = [
+            (attr & MOUNT_ATTR_NOSUID != 0, "nosuid"),
+            (attr & MOUNT_ATTR_NODEV != 0, "nodev"),
+            (attr & MOUNT_ATTR_NOEXEC != 0, "noexec"),
+            (attr & MOUNT_ATTR__ATIME == MOUNT_ATTR_NOATIME, "noatime"),
+            (attr & MOUNT_ATTR_NODIRATIME != 0, "nodiratime"),
+            (attr & MOUNT_ATTR__ATIME == MOUNT_ATTR_RELATIME, "relatime"),
+            (attr & MOUNT_ATTR_NOSYMFOLLOW != 0, "nosymfollow"),
+            (attr & MOUNT_ATTR_IDMAP != 0, "idmapped"),
+        ];
+        options.extend(flags.iter().filter(|f| f.0).map(|f| f.1.to_owned()));
+    }
+
+    /// Per-superblock flags, without `ro` or `rw`.
+    fn sb_flag_options(&self, options: &mut Vec<String>) {
+        let sb_flags = self.sb_flags();
+        let flags = [
+            (sb_flags & SB_SYNCHRONOUS != 0, "sync"),
+            (sb_flags & SB_DIRSYNC != 0, "dirsync"),
+            (sb_flags & SB_LAZYTIME != 0, "lazytime"),
+        ];
+        options.extend(flags.iter().filter(|f| f.0).map(|f| f.1.to_owned()));
+    }
+
+    /// Builds the same entry as in `/proc/mounts`.
+    fn to_linux_mount(&self) -> LinuxMount {
+        let read_only =
+            self.mnt_attr() & MOUNT_ATTR_RDONLY != 0 || self.sb_flags() & SB_RDONLY != 0;
+        let mut mount_options = vec![String::from(if read_only { "ro" } else { "rw" })];
+        self.sb_flag_options(&mut mount_options);
+        self.mount_flags(&mut mount_options);
+        mount_options.extend(self.fs_options());
+
+        LinuxMount {
+            spec: OsString::from_vec(self.sb_source().to_vec()),
+            mount_point: PathBuf::from(OsString::from_vec(self.mnt_point().to_vec())),
+            fs_type: self.fs_type(),
+            mount_options,
+            dump_fs_freq: 0,
+            fsck_fs_passno: 0,
+            raw_spec: OsString::from_vec(escape_octal(self.sb_source()).into_owned()),
+            raw_mount_point: OsString::from_vec(escape_octal(self.mnt_point()).into_owned()),
+        }
+    }
+
+    /// Builds the same entry as in `/proc/self/mountinfo`.
+    fn to_mountinfo(&self) -> MountInfo {
+        let mut mount_options = vec![String::from(if self.mnt_attr() & MOUNT_ATTR_RDONLY != 0 {
+            "ro"
+        } else {
+            "rw"
+        })];
+        self.mount_flags(&mut mount_options);
+
+        let mut super_options = vec![String::from(if self.sb_flags() & SB_RDONLY != 0 {
+            "ro"
+        } else {
+            "rw"
+        })];
+        self.sb_flag_options(&mut super_options);
+        super_options.extend(self.fs_options());
+
+        let propagation = self.mnt_propagation();
+        let mut tags = Vec::new();
+        if propagation & MS_SHARED != 0 {
+            tags.push(PropagationTag::Shared(self.mnt_peer_group() as u32));
+        }
+        if propagation & MS_SLAVE != 0 {
+            let master = self.mnt_master() as u32;
+            tags.push(PropagationTag::Master(master));
+            let from = self.propagate_from() as u32;
+            if from != 0 && from != master {
+                tags.push(PropagationTag::PropagateFrom(from));
+            }
+        }
+        if propagation & MS_UNBINDABLE != 0 {
+            tags.push(PropagationTag::Unbindable);
+        }
+
+        let (major, minor) = self.sb_dev();
+        MountInfo {
+            mount_id: self.mnt_id_old(),
+            parent_id: self.mnt_parent_id_old(),
+            major,
+            minor,
+            root: self.mnt_root(),
+            mount_point: PathBuf::from(OsString::from_vec(self.mnt_point().to_vec())),
+            mount_options,
+            propagation: tags,
+            fs_type: self.fs_type(),
+            spec: OsString::from_vec(self.sb_source().to_vec()),
+            super_options,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use pretty_assertions::assert_eq;
+
+    use super::StatmountTable;
+    use crate::{
+        mount::{list_current_mounts, LinuxMount},
+        mountinfo::{list_current_mountinfo, MountInfo},
+        table::MountTableSource,
+    };
+
+    #[test]
+    fn same_as_proc() {
+        let Ok(mut table) = StatmountTable::<LinuxMount>::new() else {
+            eprintln!("listmount/statmount not supported, skipping the test");
+            return;
+        };
+        let expected: HashSet<_> = list_current_mounts().unwrap().into_iter().collect();
+        let actual: HashSet<_> = table.read().unwrap().into_iter().collect();
+        assert_eq!(expected, actual);
+
+        let mut table = StatmountTable::<MountInfo>::new().unwrap();
+        let expected: HashSet<_> = list_current_mountinfo().unwrap().into_iter().collect();
+        let actual: HashSet<_> = table.read().unwrap().into_iter().collect();
+        assert_eq!(expected, actual);
+    }
+}
@@ -0,0 +1,321 @@
+//! Async interface, based on tokio.
+//!
+//! Instead of spawning a background thread, [`MountStream`] registers the mount table to
+//! the reactor of the tokio runtime, and yields a [`MountEvent`] each time the mounted
+//! filesystems change.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use futures_core::Stream;
+//! use mount_watcher::MountWatcher;
+//!
+//! # async fn example() {
+//! let mut stream = MountWatcher::stream().unwrap();
+//! // Get the events with poll_next, or with StreamExt::next (futures crate, tokio-stream...).
+//! # }
+//! ```
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    future::Future,
+    path::Path,
+    pin::{pin, Pin},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use tokio::{
+    io::{unix::AsyncFd, Interest},
+    time::Instant,
+};
+
+use crate::{
+    mount::{read_mount_table, LinuxMount, MountEntry, OnInvalidLine, ReadError},
+    mountinfo::MountInfo,
+    watch::{diff_mounts, ErrorImpl, SetupError},
+    MountEvent, MountWatcher,
+};
+
+type Item<M> = Result<MountEvent<M>, ReadError>;
+type NextFuture<M> = Pin<Box<dyn Future<Output = (Item<M>, Box<Inner<M>>)> + Send>>;
+
+/// Stream of [`MountEvent`]s, driven by the tokio runtime.
+///
+/// Use [`MountWatcher::stream`] or [`MountWatcher::stream_mountinfo`] to create it.
+///
+/// # Coalescing
+///
+/// Call [`coalesce`](Self::coalesce) right after receiving an event to get the same
+/// behavior as [`WatchControl::Coalesce`](crate::WatchControl::Coalesce).
+///
+/// # Stopping
+///
+/// Drop the stream to stop watching.
+/// The stream also ends after yielding an error.
+pub struct MountStream<M: MountEntry = LinuxMount> {
+    state: StreamState<M>,
+}
+
+enum StreamState<M: MountEntry> {
+    /// Waiting for the next call to `poll_next`.
+    Idle(Box<Inner<M>>),
+    /// Waiting for the next event.
+    Running(NextFuture<M>),
+    /// An error has occurred, no more events.
+    Done,
+}
+
+struct Inner<M: MountEntry> {
+    fd: AsyncFd<File>,
+    known_mounts: HashSet<M>,
+    /// The mounts of the last event, which are saved on the next poll (unless we coalesce).
+    pending_mounts: Option<HashSet<M>>,
+    coalesce_delay: Option<Duration>,
+    coalesce_deadline: Option<Instant>,
+    initial: bool,
+    /// Number of times that `known_mounts` has been updated.
+    generation: u64,
+}
+
+impl MountWatcher {
+    /// Watches the list of mounted filesystems from an async context.
+    ///
+    /// Unlike [`new`](Self::new), this does not spawn a thread: the mount table is
+    /// registered to the reactor of the current tokio runtime.
+    ///
+    /// # Panics
+    /// This function panics if it is not called from within a tokio runtime,
+    /// with IO and time enabled.
+    pub fn stream() -> Result<MountStream, SetupError> {
+        MountStream::new()
+    }
+
+    /// Watches `/proc/self/mountinfo` from an async context.
+    ///
+    /// See [`stream`](Self::stream) and [`new_mountinfo`](Self::new_mountinfo).
+    pub fn stream_mountinfo() -> Result<MountStream<MountInfo>, SetupError> {
+        MountStream::new()
+    }
+}
+
+impl<M: MountEntry> MountStream<M> {
+    fn new() -> Result<Self, SetupError> {
+        Self::open(Path::new(M::TABLE_PATH))
+    }
+
+    /// Watches the mount table at `path`.
+    fn open(path: &Path) -> Result<Self, SetupError> {
+        let file =
+            File::open(path).map_err(|e| SetupError(ErrorImpl::MountRead(ReadError::Io(e))))?;
+        // Like in the epoll-based watcher, a mount or unmount is a PRIORITY event.
+        let fd = AsyncFd::with_interest(file, Interest::PRIORITY)
+            .map_err(|e| SetupError(ErrorImpl::PollInit(e)))?;
+        let inner = Inner {
+            fd,
+            known_mounts: HashSet::with_capacity(8),
+            pending_mounts: None,
+            coalesce_delay: None,
+            coalesce_deadline: None,
+            initial: true,
+            generation: 0,
+        };
+        Ok(Self {
+            state: StreamState::Idle(Box::new(inner)),
+        })
+    }
+
+    /// Coalesces the last event with the changes that will occur during the given delay.
+    ///
+    /// After the delay, the stream yields a new event that includes the mounts/unmounts
+    /// of the last event, in addition to the new ones.
+    /// This is the equivalent of returning [`WatchControl::Coalesce`](crate::WatchControl::Coalesce)
+    /// from a callback.
+    ///
+    /// This must be called after receiving an event and before polling the stream again,
+    /// otherwise it has no effect.
+    pub fn coalesce(&mut self, delay: Duration) {
+        match &mut self.state {
+            StreamState::Idle(inner) if inner.pending_mounts.is_some() => {
+                inner.coalesce_delay = Some(delay);
+            }
+            _ => log::warn!("coalesce() called while no event is pending, ignoring it"),
+        }
+    }
+}
+
+impl<M: MountEntry> Stream for MountStream<M> {
+    type Item = Item<M>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match std::mem::replace(&mut self.state, StreamState::Done) {
+                StreamState::Idle(inner) => {
+                    self.state = StreamState::Running(Box::pin(inner.next_event()));
+                }
+                StreamState::Running(mut fut) => {
+                    return match fut.as_mut().poll(cx) {
+                        Poll::Ready((item, inner)) => {
+                            if item.is_ok() {
+                                self.state = StreamState::Idle(inner);
+                            }
+                            Poll::Ready(Some(item))
+                        }
+                        Poll::Pending => {
+                            self.state = StreamState::Running(fut);
+                            Poll::Pending
+                        }
+                    };
+                }
+                StreamState::Done => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+impl<M: MountEntry> Inner<M> {
+    async fn next_event(mut self: Box<Self>) -> (Item<M>, Box<Self>) {
+        let res = self.wait_for_changes().await;
+        (res, self)
+    }
+
+    async fn wait_for_changes(&mut self) -> Item<M> {
+        // The previous event has been handled: save its mounts, or coalesce.
+        if let Some(mounts) = self.pending_mounts.take() {
+            match self.coalesce_delay.take() {
+                Some(delay) => {
+                    log::trace!("start coalescing for {delay:?}");
+                    self.coalesce_deadline = Some(Instant::now() + delay);
+                }
+                None => {
+                    self.known_mounts = mounts;
+                    self.generation += 1;
+                }
+            }
+        }
+
+        let mut initial = std::mem::take(&mut self.initial);
+        loop {
+            let coalesced = match self.coalesce_deadline.take() {
+                Some(deadline) => {
+                    // Ignore the changes until the deadline, we'll read them all at once.
+                    tokio::time::sleep_until(deadline).await;
+                    self.clear_readiness().await;
+                    true
+                }
+                None if initial => false,
+                None => {
+                    let mut guard = self.fd.ready(Interest::PRIORITY).await?;
+                    guard.clear_ready();
+                    false
+                }
+            };
+
+            // Skip the invalid lines: one odd entry should not stop the watcher.
+            let mounts = read_mount_table(self.fd.get_mut(), OnInvalidLine::Skip)?;
+            let mounts = HashSet::from_iter(mounts);
+            if let Some(mut event) = diff_mounts(&self.known_mounts, &mounts, coalesced, initial) {
+                event.generation = self.generation + 1;
+                self.pending_mounts = Some(mounts);
+                return Ok(event);
+            }
+            initial = false;
+        }
+    }
+
+    /// Clears the readiness of the file, if it is ready, without waiting.
+    async fn clear_readiness(&self) {
+        std::future::poll_fn(|cx| {
+            let ready = pin!(self.fd.ready(Interest::PRIORITY));
+            if let Poll::Ready(Ok(mut guard)) = ready.poll(cx) {
+                guard.clear_ready();
+            }
+            Poll::Ready(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::Path, pin::Pin, time::Duration};
+
+    use futures_core::Stream;
+    use pretty_assertions::assert_eq;
+
+    use super::MountStream;
+    use crate::{
+        mount::{list_current_mounts, LinuxMount},
+        test_util::{in_mount_namespace, sys_mount, sys_umount},
+        MountEvent, MountWatcher,
+    };
+
+    async fn next(stream: &mut MountStream) -> MountEvent {
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx))
+            .await
+            .expect("the stream should yield an event")
+            .unwrap()
+    }
+
+    #[test]
+    fn initial_event() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut stream = MountWatcher::stream().unwrap();
+            let event = next(&mut stream).await;
+            assert!(event.initial);
+            assert!(!event.coalesced);
+            assert!(event.unmounted.is_empty());
+
+            let expected: HashSet<_> = list_current_mounts().unwrap().into_iter().collect();
+            let actual: HashSet<_> = event.mounted.into_iter().collect();
+            assert_eq!(expected, actual);
+        });
+    }
+
+    #[test]
+    fn coalesce() {
+        in_mount_namespace(|| {
+            let dir =
+                std::env::temp_dir().join(format!("mount-watcher-stream-{}", std::process::id()));
+            let (a, b) = (dir.join("a"), dir.join("b"));
+            std::fs::create_dir_all(&a).unwrap();
+            std::fs::create_dir_all(&b).unwrap();
+
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            rt.block_on(async {
+                let table = Path::new("/proc/thread-self/mounts");
+                let mut stream = MountStream::<LinuxMount>::open(table).unwrap();
+                assert!(next(&mut stream).await.initial);
+
+                sys_mount(Some("none"), &a, Some("tmpfs"), 0);
+                let event = next(&mut stream).await;
+                assert!(!event.coalesced);
+                assert_eq!(event.mounted.len(), 1);
+
+                // the next event includes the mounts of the previous one
+                stream.coalesce(Duration::from_millis(200));
+                sys_mount(Some("none"), &b, Some("tmpfs"), 0);
+                let event = next(&mut stream).await;
+                assert!(event.coalesced);
+                let mut mount_points: Vec<_> =
+                    event.mounted.into_iter().map(|m| m.mount_point).collect();
+                mount_points.sort();
+                assert_eq!(mount_points, [a.clone(), b.clone()]);
+                assert!(event.unmounted.is_empty());
+            });
+
+            sys_umount(&a);
+            sys_umount(&b);
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
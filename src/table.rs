@@ -0,0 +1,307 @@
+//! Sources of mount tables.
+//!
+//! By default, the watcher reads `/proc/mounts` (or `/proc/self/mountinfo`), see [`ProcMountTable`].
+//! With [`MountWatcherBuilder::source`](crate::MountWatcherBuilder::source), you can plug
+//! another [`MountTableSource`], for instance a [`FakeMountTable`] in your tests.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use mount_watcher::{mount::LinuxMount, table::FakeMountTable, MountWatcher};
+//!
+//! let table = FakeMountTable::new().unwrap();
+//! let events = MountWatcher::builder()
+//!     .source(table.clone())
+//!     .build_channel()
+//!     .unwrap();
+//! assert!(events.recv().unwrap().initial);
+//!
+//! table.mount(LinuxMount::parse("/dev/sdb1 /mnt/usb vfat rw 0 0").unwrap());
+//! table.notify();
+//! let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+//! assert_eq!(event.mounted.len(), 1);
+//! ```
+
+use std::{
+    ffi::OsStr,
+    fmt,
+    fs::File,
+    io::{self, Read, Write},
+    marker::PhantomData,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use mio::{
+    event::Source,
+    unix::{pipe, SourceFd},
+    Interest, Registry, Token,
+};
+
+use crate::mount::{read_mount_table, LinuxMount, MountEntry, OnInvalidLine, ReadError};
+
+/// Provides the list of mounts, and a way to know when it changes.
+pub trait MountTableSource<M>: Send + 'static {
+    /// Reads the current mounts.
+    fn read(&mut self) -> Result<Vec<M>, ReadError>;
+
+    /// Returns a file descriptor that becomes ready when the mounts change.
+    fn readiness_fd(&self) -> BorrowedFd<'_>;
+
+    /// Returns the readiness that [`readiness_fd`](Self::readiness_fd) is expected to
+    /// have when the mounts change.
+    fn readiness_interest(&self) -> Interest;
+
+    /// Reads the mounts that have been mounted and unmounted since the previous read,
+    /// if the source knows them.
+    ///
+    /// The watcher builds its events from these changes, instead of comparing the whole
+    /// table with the previous one. When this returns `None`, which is the default, the
+    /// watcher calls [`read`](Self::read) instead.
+    fn read_changes(&mut self) -> Result<Option<TableChanges<M>>, ReadError> {
+        Ok(None)
+    }
+}
+
+/// Mounts that have been mounted and unmounted, see [`MountTableSource::read_changes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableChanges<M> {
+    /// The new mounts.
+    pub mounted: Vec<M>,
+    /// The mounts that have been unmounted.
+    pub unmounted: Vec<M>,
+}
+
+impl<M> Default for TableChanges<M> {
+    fn default() -> Self {
+        Self {
+            mounted: Vec::new(),
+            unmounted: Vec::new(),
+        }
+    }
+}
+
+impl<M: PartialEq> TableChanges<M> {
+    /// Returns `true` if nothing has changed.
+    pub fn is_empty(&self) -> bool {
+        self.mounted.is_empty() && self.unmounted.is_empty()
+    }
+
+    /// Adds the `next` changes, which happened after these ones.
+    ///
+    /// A mount that is unmounted after being mounted is removed from both lists,
+    /// and conversely.
+    pub(crate) fn merge(&mut self, next: TableChanges<M>) {
+        for mount in next.unmounted {
+            match self.mounted.iter().position(|m| *m == mount) {
+                Some(i) => {
+                    self.mounted.swap_remove(i);
+                }
+                None => self.unmounted.push(mount),
+            }
+        }
+        for mount in next.mounted {
+            match self.unmounted.iter().position(|m| *m == mount) {
+                Some(i) => {
+                    self.unmounted.swap_remove(i);
+                }
+                None => self.mounted.push(mount),
+            }
+        }
+    }
+}
+
+impl<M> fmt::Debug for dyn MountTableSource<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MountTableSource")
+    }
+}
+
+/// Mount table provided by the kernel, such as `/proc/mounts`.
+#[derive(Debug)]
+pub struct ProcMountTable<M = LinuxMount> {
+    file: File,
+    entry: PhantomData<fn() -> M>,
+}
+
+impl<M: MountEntry> ProcMountTable<M> {
+    /// Opens the default table of `M`: `/proc/mounts` or `/proc/self/mountinfo`.
+    pub fn new() -> io::Result<Self> {
+        Self::open(M::TABLE_PATH)
+    }
+
+    /// Opens the mount table at `path`, which must support `epoll` like `/proc/mounts`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            file: File::open(path)?,
+            entry: PhantomData,
+        })
+    }
+
+    /// Opens the mount table of another process: `/proc/<pid>/mounts` or `/proc/<pid>/mountinfo`.
+    ///
+    /// The table stays readable after the process exits, use a [`MountWatcherBuilder::pid`]
+    /// to detect it.
+    ///
+    /// [`MountWatcherBuilder::pid`]: crate::MountWatcherBuilder::pid
+    pub fn for_pid(pid: u32) -> io::Result<Self> {
+        Self::open(
+            Path::new("/proc")
+                .join(pid.to_string())
+                .join(table_name::<M>()),
+        )
+    }
+
+    /// Opens the mount table of a mount namespace, given a file descriptor such as
+    /// `/proc/<pid>/ns/mnt`.
+    ///
+    /// This requires `CAP_SYS_ADMIN` and `CAP_SYS_CHROOT`: a short-lived thread enters the
+    /// namespace to open its table. The namespace is kept alive until the table is dropped.
+    pub fn for_namespace(ns: impl AsFd) -> io::Result<Self> {
+        let ns = ns.as_fd().try_clone_to_owned()?;
+        let path = Path::new("/proc/thread-self").join(table_name::<M>());
+        std::thread::spawn(move || {
+            // Threads share their filesystem info, which prevents them from entering another
+            // mount namespace. This thread stops sharing it before calling setns.
+            // SAFETY: no pointer is involved
+            if unsafe { libc::unshare(libc::CLONE_FS) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: the fd is valid until the end of the thread
+            if unsafe { libc::setns(ns.as_raw_fd(), libc::CLONE_NEWNS) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Self::open(path)
+        })
+        .join()
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "the thread that enters the namespace has panicked",
+            )
+        })?
+    }
+}
+
+/// Returns the file name of the table of `M` in `/proc/<pid>`: `mounts` or `mountinfo`.
+fn table_name<M: MountEntry>() -> &'static OsStr {
+    Path::new(M::TABLE_PATH)
+        .file_name()
+        .expect("TABLE_PATH should end with a file name")
+}
+
+impl<M: MountEntry> MountTableSource<M> for ProcMountTable<M> {
+    fn read(&mut self) -> Result<Vec<M>, ReadError> {
+        // Skip the invalid lines: one odd entry should not stop the watcher.
+        read_mount_table(&mut self.file, OnInvalidLine::Skip)
+    }
+
+    fn readiness_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+
+    fn readiness_interest(&self) -> Interest {
+        // According to `man proc_mounts`, a filesystem mount or unmount causes
+        // `poll` and `epoll_wait` to mark the file as having a PRIORITY event.
+        Interest::PRIORITY
+    }
+}
+
+/// In-memory mount table, to test the code that uses a watcher without mounting anything.
+///
+/// All the clones share the same mounts. Modify them with [`mount`](Self::mount) and
+/// [`unmount`](Self::unmount), then call [`notify`](Self::notify) to wake the watcher up.
+#[derive(Clone)]
+pub struct FakeMountTable<M = LinuxMount> {
+    inner: Arc<FakeInner<M>>,
+}
+
+struct FakeInner<M> {
+    mounts: Mutex<Vec<M>>,
+    tx: pipe::Sender,
+    rx: pipe::Receiver,
+}
+
+impl<M: MountEntry> FakeMountTable<M> {
+    /// Creates an empty table.
+    pub fn new() -> io::Result<Self> {
+        let (tx, rx) = pipe::new()?;
+        Ok(Self {
+            inner: Arc::new(FakeInner {
+                mounts: Mutex::new(Vec::new()),
+                tx,
+                rx,
+            }),
+        })
+    }
+
+    /// Adds a mount to the table.
+    pub fn mount(&self, mount: M) {
+        self.inner.mounts.lock().unwrap().push(mount);
+    }
+
+    /// Removes a mount from the table. Returns `false` if it was not in the table.
+    pub fn unmount(&self, mount: &M) -> bool {
+        let mut mounts = self.inner.mounts.lock().unwrap();
+        match mounts.iter().position(|m| m == mount) {
+            Some(i) => {
+                mounts.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces all the mounts of the table.
+    pub fn set_mounts(&self, mounts: impl IntoIterator<Item = M>) {
+        *self.inner.mounts.lock().unwrap() = mounts.into_iter().collect();
+    }
+
+    /// Returns the mounts of the table.
+    pub fn mounts(&self) -> Vec<M> {
+        self.inner.mounts.lock().unwrap().clone()
+    }
+
+    /// Notifies the watcher that the table has changed.
+    pub fn notify(&self) {
+        if let Err(e) = (&self.inner.tx).write(&[1]) {
+            // the pipe is full: the watcher will be notified anyway
+            log::debug!("failed to notify the fake mount table: {e}");
+        }
+    }
+}
+
+impl<M: MountEntry> MountTableSource<M> for FakeMountTable<M> {
+    fn read(&mut self) -> Result<Vec<M>, ReadError> {
+        // drain the notifications
+        let mut buf = [0; 64];
+        while let Ok(1..) = (&self.inner.rx).read(&mut buf) {}
+        Ok(self.mounts())
+    }
+
+    fn readiness_fd(&self) -> BorrowedFd<'_> {
+        self.inner.rx.as_fd()
+    }
+
+    fn readiness_interest(&self) -> Interest {
+        Interest::READABLE
+    }
+}
+
+impl<M> fmt::Debug for FakeMountTable<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FakeMountTable").finish_non_exhaustive()
+    }
+}
+
+/// Registers the readiness fd of a [`MountTableSource`] to a [`mio::Poll`].
+pub(crate) fn register_source<M: MountEntry>(
+    source: &dyn MountTableSource<M>,
+    registry: &Registry,
+    token: Token,
+) -> io::Result<()> {
+    let fd: RawFd = source.readiness_fd().as_raw_fd();
+    SourceFd(&fd).register(registry, token, source.readiness_interest())
+}
@@ -0,0 +1,61 @@
+//! Helpers shared by the unit tests.
+
+use std::{ffi::CString, os::unix::ffi::OsStrExt, path::Path};
+
+use crate::mount::LinuxMount;
+
+/// Parses a line of `/proc/mounts`.
+pub(crate) fn mount(line: &str) -> LinuxMount {
+    LinuxMount::parse(line).unwrap()
+}
+
+/// Runs `f` on a new thread, in a new mount namespace, to leave the mounts of the other
+/// tests alone. The test is skipped if the namespace cannot be created.
+///
+/// Use `/proc/thread-self` to see the mounts of the namespace: `/proc/self` is the main thread.
+pub(crate) fn in_mount_namespace(f: impl FnOnce() + Send + 'static) {
+    std::thread::spawn(|| {
+        // SAFETY: no pointer is involved
+        if unsafe { libc::unshare(libc::CLONE_NEWNS) } != 0 {
+            eprintln!("cannot create a mount namespace, skipping the test");
+            return;
+        }
+        sys_mount(None, Path::new("/"), None, libc::MS_REC | libc::MS_PRIVATE);
+        f()
+    })
+    .join()
+    .unwrap();
+}
+
+/// Calls `mount(2)`, and panics if it fails.
+pub(crate) fn sys_mount(
+    source: Option<&str>,
+    target: &Path,
+    fs_type: Option<&str>,
+    flags: libc::c_ulong,
+) {
+    let cstr = |s: &[u8]| CString::new(s).unwrap();
+    let source = source.map(|s| cstr(s.as_bytes()));
+    let target = cstr(target.as_os_str().as_bytes());
+    let fs_type = fs_type.map(|s| cstr(s.as_bytes()));
+    let ptr = |s: &Option<CString>| s.as_ref().map_or(std::ptr::null(), |s| s.as_ptr());
+    // SAFETY: the strings are valid for the duration of the call
+    let res = unsafe {
+        libc::mount(
+            ptr(&source),
+            target.as_ptr(),
+            ptr(&fs_type),
+            flags,
+            std::ptr::null(),
+        )
+    };
+    assert_eq!(res, 0, "{}", std::io::Error::last_os_error());
+}
+
+/// Calls `umount(2)`, and panics if it fails.
+pub(crate) fn sys_umount(target: &Path) {
+    let target = CString::new(target.as_os_str().as_bytes()).unwrap();
+    // SAFETY: the string is valid for the duration of the call
+    let res = unsafe { libc::umount(target.as_ptr()) };
+    assert_eq!(res, 0, "{}", std::io::Error::last_os_error());
+}
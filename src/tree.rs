@@ -0,0 +1,440 @@
+//! Mounts organized as a tree, with the parent IDs of `/proc/self/mountinfo`.
+//!
+//! Mounts nest (`/var` is mounted in `/`) and stack (a filesystem can be mounted on top of
+//! another one). [`MountTree`] gives access to these relationships, and finds the mounts
+//! that are hidden by another mount.
+//!
+//! [`MountTree::diff`] compares two trees and groups the mounted and unmounted filesystems by
+//! subtree, for instance to know that `/var/lib/containers/x` has been unmounted along with
+//! all the mounts below it. The events of a watcher can be grouped in the same way, see
+//! [`MountEvent::unmounted_subtrees`].
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    mount::{MountEntry, ReadError},
+    mountinfo::{list_current_mountinfo, MountInfo},
+    MountChange, MountEvent, MountSnapshot,
+};
+
+/// A tree of mounts, built from their IDs and the IDs of their parents.
+///
+/// The mounts are identified by their [`mount_id`](MountInfo::mount_id). A mount whose parent
+/// is not in the table, for instance the root of a chroot, is a root of the tree.
+#[derive(Debug, Clone)]
+pub struct MountTree {
+    mounts: Vec<MountInfo>,
+    by_id: HashMap<u32, usize>,
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    roots: Vec<usize>,
+}
+
+/// A mount and the mounts below it, see [`MountTree::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subtree {
+    /// The topmost mount of the subtree.
+    pub root: MountInfo,
+    /// The other mounts of the subtree, parents first.
+    pub descendants: Vec<MountInfo>,
+}
+
+/// The differences between two trees, see [`MountTree::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeDiff {
+    /// The subtrees that are only in the new tree.
+    pub mounted: Vec<Subtree>,
+    /// The subtrees that are only in the old tree.
+    pub unmounted: Vec<Subtree>,
+    /// The mounts whose description has changed, e.g. after a remount.
+    pub changed: Vec<MountChange<MountInfo>>,
+}
+
+impl MountTree {
+    /// Builds the tree of the given mounts.
+    ///
+    /// The children of each mount are kept in the order of `mounts`.
+    pub fn new(mounts: impl IntoIterator<Item = MountInfo>) -> Self {
+        let mounts: Vec<MountInfo> = mounts.into_iter().collect();
+        let by_id: HashMap<u32, usize> = mounts
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (m.mount_id, i))
+            .collect();
+        let parents: Vec<Option<usize>> = mounts
+            .iter()
+            .map(|m| match by_id.get(&m.parent_id) {
+                Some(&p) if m.parent_id != m.mount_id => Some(p),
+                _ => None,
+            })
+            .collect();
+        let mut children = vec![Vec::new(); mounts.len()];
+        let mut roots = Vec::new();
+        for (i, parent) in parents.iter().enumerate() {
+            match parent {
+                Some(p) => children[*p].push(i),
+                None => roots.push(i),
+            }
+        }
+        Self {
+            mounts,
+            by_id,
+            parents,
+            children,
+            roots,
+        }
+    }
+
+    /// Builds the tree of the current mount namespace.
+    pub fn current() -> Result<Self, ReadError> {
+        Ok(Self::new(list_current_mountinfo()?))
+    }
+
+    /// Returns the number of mounts.
+    pub fn len(&self) -> usize {
+        self.mounts.len()
+    }
+
+    /// Returns `true` if the tree has no mount.
+    pub fn is_empty(&self) -> bool {
+        self.mounts.is_empty()
+    }
+
+    /// Iterates over all the mounts, in the order given to [`new`](Self::new).
+    pub fn iter(&self) -> impl Iterator<Item = &MountInfo> {
+        self.mounts.iter()
+    }
+
+    /// Returns the mount with the given ID.
+    pub fn get(&self, mount_id: u32) -> Option<&MountInfo> {
+        self.by_id.get(&mount_id).map(|&i| &self.mounts[i])
+    }
+
+    /// Returns the mounts that have no parent in the tree, usually only `/`.
+    pub fn roots(&self) -> impl Iterator<Item = &MountInfo> {
+        self.roots.iter().map(|&i| &self.mounts[i])
+    }
+
+    /// Returns the parent of a mount, or `None` for a root.
+    pub fn parent(&self, mount_id: u32) -> Option<&MountInfo> {
+        let i = *self.by_id.get(&mount_id)?;
+        self.parents[i].map(|p| &self.mounts[p])
+    }
+
+    /// Returns the mounts whose parent is the given mount.
+    pub fn children(&self, mount_id: u32) -> impl Iterator<Item = &MountInfo> {
+        let children = match self.by_id.get(&mount_id) {
+            Some(&i) => self.children[i].as_slice(),
+            None => &[],
+        };
+        children.iter().map(|&c| &self.mounts[c])
+    }
+
+    /// Returns the parent of a mount, then the parent of the parent, and so on up to the root.
+    pub fn ancestors(&self, mount_id: u32) -> impl Iterator<Item = &MountInfo> {
+        let start = self.by_id.get(&mount_id).and_then(|&i| self.parents[i]);
+        std::iter::successors(start, |&i| self.parents[i])
+            // stop on cycles, which only exist in invalid tables
+            .take(self.mounts.len())
+            .map(|i| &self.mounts[i])
+    }
+
+    /// Returns a mount and all the mounts below it, parents first (depth-first).
+    ///
+    /// Returns nothing if the mount is not in the tree.
+    pub fn subtree(&self, mount_id: u32) -> impl Iterator<Item = &MountInfo> {
+        let mut stack: Vec<usize> = self.by_id.get(&mount_id).copied().into_iter().collect();
+        std::iter::from_fn(move || {
+            let i = stack.pop()?;
+            stack.extend(self.children[i].iter().rev());
+            Some(i)
+        })
+        .take(self.mounts.len())
+        .map(|i| &self.mounts[i])
+    }
+
+    /// Returns `true` if the mount cannot be accessed with its mount point, because another
+    /// filesystem has been mounted over it or over one of its parent directories.
+    pub fn is_shadowed(&self, mount_id: u32) -> bool {
+        let Some(&i) = self.by_id.get(&mount_id) else {
+            return false;
+        };
+        let mut path = None;
+        std::iter::successors(Some(i), |&i| self.parents[i])
+            .take(self.mounts.len())
+            .any(|i| {
+                let covered = self.is_covered(i, path);
+                path = Some(i);
+                covered
+            })
+    }
+
+    /// Returns the mounts that are shadowed, see [`is_shadowed`](Self::is_shadowed).
+    pub fn shadowed(&self) -> impl Iterator<Item = &MountInfo> {
+        self.mounts.iter().filter(|m| self.is_shadowed(m.mount_id))
+    }
+
+    /// Returns `true` if a mount is directly hidden by another one, without looking at its parents.
+    ///
+    /// `path` is the child that leads to the mount we are interested in, which can't hide it.
+    fn is_covered(&self, i: usize, path: Option<usize>) -> bool {
+        let mount = &self.mounts[i];
+        // mounted on the root of this mount
+        let on_top = self.children[i]
+            .iter()
+            .any(|&c| Some(c) != path && self.mounts[c].mount_point == mount.mount_point);
+        // Mounted on a parent directory: if `/a` and `/a/b` have the same parent, `/a/b` has
+        // been mounted first (otherwise its parent would be `/a`).
+        let on_parent_dir = self.parents[i].is_some_and(|p| {
+            self.children[p].iter().any(|&s| {
+                let sibling = &self.mounts[s].mount_point;
+                sibling != &mount.mount_point && mount.mount_point.starts_with(sibling)
+            })
+        });
+        on_top || on_parent_dir
+    }
+
+    /// Compares `self` (the old tree) and `new`.
+    ///
+    /// The mounts are matched with [`MountEntry::is_same_mount`]: by ID, device, root and mount
+    /// point, because the kernel reuses the IDs. The new mounts are grouped by subtree: the root
+    /// of each [`Subtree`] is a new mount whose parent was already there, and its descendants
+    /// are the new mounts below it. The same goes for the unmounted filesystems.
+    pub fn diff(&self, new: &MountTree) -> TreeDiff {
+        let changed = self
+            .mounts
+            .iter()
+            .filter_map(|old| {
+                let new = new.get_same(old)?;
+                (new != old).then(|| MountChange {
+                    old: old.clone(),
+                    new: new.clone(),
+                })
+            })
+            .collect();
+        TreeDiff {
+            mounted: new.subtrees_without(self),
+            unmounted: self.subtrees_without(new),
+            changed,
+        }
+    }
+
+    /// Returns the mount of `self` that is the same as `mount`, possibly with other options.
+    fn get_same(&self, mount: &MountInfo) -> Option<&MountInfo> {
+        self.get(mount.mount_id).filter(|m| m.is_same_mount(mount))
+    }
+
+    /// Groups the mounts of `self` that are not in `other` by subtree.
+    fn subtrees_without(&self, other: &MountTree) -> Vec<Subtree> {
+        let only_here: HashSet<usize> = (0..self.mounts.len())
+            .filter(|&i| other.get_same(&self.mounts[i]).is_none())
+            .collect();
+        let mut subtrees = Vec::new();
+        for i in 0..self.mounts.len() {
+            let is_top =
+                only_here.contains(&i) && self.parents[i].map_or(true, |p| !only_here.contains(&p));
+            if is_top {
+                let mut mounts = self
+                    .subtree(self.mounts[i].mount_id)
+                    .filter(|m| only_here.contains(&self.by_id[&m.mount_id]))
+                    .cloned();
+                subtrees.push(Subtree {
+                    root: mounts.next().unwrap(),
+                    descendants: mounts.collect(),
+                });
+            }
+        }
+        subtrees
+    }
+}
+
+impl FromIterator<MountInfo> for MountTree {
+    fn from_iter<T: IntoIterator<Item = MountInfo>>(iter: T) -> Self {
+        Self::new(iter)
+    }
+}
+
+impl Subtree {
+    /// Iterates over all the mounts of the subtree, starting with the root.
+    pub fn iter(&self) -> impl Iterator<Item = &MountInfo> {
+        std::iter::once(&self.root).chain(&self.descendants)
+    }
+}
+
+impl TreeDiff {
+    /// Returns `true` if the two trees are identical.
+    pub fn is_empty(&self) -> bool {
+        self.mounted.is_empty() && self.unmounted.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl MountSnapshot<MountInfo> {
+    /// Builds the tree of the mounts of the snapshot.
+    ///
+    /// The children of each mount are sorted by mount ID.
+    pub fn tree(&self) -> MountTree {
+        let mut mounts: Vec<MountInfo> = self.mounts().iter().cloned().collect();
+        mounts.sort_by_key(|m| m.mount_id);
+        MountTree::new(mounts)
+    }
+}
+
+impl MountEvent<MountInfo> {
+    /// Groups the new mounts by subtree, see [`MountTree::diff`].
+    pub fn mounted_subtrees(&self) -> Vec<Subtree> {
+        group_subtrees(&self.mounted)
+    }
+
+    /// Groups the removed mounts by subtree, see [`MountTree::diff`].
+    ///
+    /// For instance, if a container has been stopped, the root of the subtree is the
+    /// root filesystem of the container, and the descendants are its volumes.
+    pub fn unmounted_subtrees(&self) -> Vec<Subtree> {
+        group_subtrees(&self.unmounted)
+    }
+}
+
+/// Groups mounts by subtree, with their parent IDs.
+fn group_subtrees(mounts: &[MountInfo]) -> Vec<Subtree> {
+    let mut mounts = mounts.to_vec();
+    mounts.sort_by_key(|m| m.mount_id);
+    let tree = MountTree::new(mounts);
+    tree.roots
+        .iter()
+        .map(|&i| {
+            let mut mounts = tree.subtree(tree.mounts[i].mount_id).cloned();
+            Subtree {
+                root: mounts.next().unwrap(),
+                descendants: mounts.collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use pretty_assertions::assert_eq;
+
+    use super::{group_subtrees, MountTree};
+    use crate::{mount::parse_table, mount::OnInvalidLine, mountinfo::MountInfo};
+
+    fn parse(content: &str) -> Vec<MountInfo> {
+        let mut mounts = Vec::new();
+        parse_table(content.as_bytes(), &mut mounts, OnInvalidLine::Fail).unwrap();
+        mounts
+    }
+
+    fn ids<'a>(mounts: impl Iterator<Item = &'a MountInfo>) -> Vec<u32> {
+        mounts.map(|m| m.mount_id).collect()
+    }
+
+    fn mount_points<'a>(mounts: impl Iterator<Item = &'a MountInfo>) -> Vec<PathBuf> {
+        mounts.map(|m| m.mount_point.clone()).collect()
+    }
+
+    const TABLE: &str = "
+1 0 8:1 / / rw - ext4 /dev/sda1 rw
+2 1 0:20 / /var rw - tmpfs var rw
+3 2 0:21 / /var/lib/containers/x rw - overlay overlay rw
+4 3 0:22 / /var/lib/containers/x/data rw - tmpfs data rw
+5 3 0:23 / /var/lib/containers/x/proc rw - proc proc rw
+6 1 0:24 / /mnt/a/b rw - tmpfs b rw
+7 1 0:25 / /mnt rw - tmpfs mnt rw
+8 2 0:26 / /var rw - tmpfs var2 rw";
+
+    #[test]
+    fn relationships() {
+        let tree = MountTree::new(parse(TABLE));
+        assert_eq!(tree.len(), 8);
+        assert_eq!(ids(tree.roots()), [1]);
+        assert_eq!(ids(tree.children(1)), [2, 6, 7]);
+        assert_eq!(ids(tree.children(2)), [3, 8]);
+        assert_eq!(ids(tree.children(42)), [0; 0]);
+        assert_eq!(tree.parent(3).map(|m| m.mount_id), Some(2));
+        assert_eq!(tree.parent(1), None);
+        assert_eq!(ids(tree.ancestors(4)), [3, 2, 1]);
+        assert_eq!(ids(tree.subtree(2)), [2, 3, 4, 5, 8]);
+        assert_eq!(ids(tree.subtree(42)), [0; 0]);
+        assert_eq!(tree.get(7).unwrap().mount_point, Path::new("/mnt"));
+    }
+
+    #[test]
+    fn shadowed() {
+        let tree = MountTree::new(parse(TABLE));
+        // /var is overmounted, and /mnt hides /mnt/a/b
+        assert_eq!(ids(tree.shadowed()), [2, 3, 4, 5, 6]);
+        assert!(!tree.is_shadowed(8));
+        assert!(!tree.is_shadowed(42));
+    }
+
+    #[test]
+    fn diff() {
+        let old = MountTree::new(parse(TABLE));
+        let new = MountTree::new(parse(
+            "
+1 0 8:1 / / ro - ext4 /dev/sda1 rw
+2 1 0:20 / /var rw - tmpfs var rw
+6 1 0:24 / /mnt/a/b rw - tmpfs b rw
+7 1 0:25 / /mnt rw - tmpfs mnt rw
+8 2 0:26 / /var rw - tmpfs var2 rw
+9 7 0:27 / /mnt/c rw - tmpfs c rw",
+        ));
+        let diff = old.diff(&new);
+        assert_eq!(diff.unmounted.len(), 1);
+        assert_eq!(
+            mount_points(diff.unmounted[0].iter()),
+            [
+                "/var/lib/containers/x",
+                "/var/lib/containers/x/data",
+                "/var/lib/containers/x/proc"
+            ]
+            .map(PathBuf::from)
+        );
+        assert_eq!(diff.mounted.len(), 1);
+        assert_eq!(diff.mounted[0].root.mount_id, 9);
+        assert!(diff.mounted[0].descendants.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].new.mount_options, ["ro"]);
+
+        // same grouping from the flat list of an event
+        let unmounted: Vec<_> = old
+            .iter()
+            .filter(|m| new.get(m.mount_id).is_none())
+            .cloned()
+            .collect();
+        assert_eq!(group_subtrees(&unmounted), diff.unmounted);
+
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn diff_reused_id() {
+        // the container is stopped, and another one gets the same IDs
+        let old = MountTree::new(parse(TABLE));
+        let new = MountTree::new(parse(
+            "
+1 0 8:1 / / rw - ext4 /dev/sda1 rw
+2 1 0:20 / /var rw - tmpfs var rw
+3 2 0:31 / /var/lib/containers/y rw - overlay overlay rw
+4 3 0:22 / /var/lib/containers/y/data rw - tmpfs data rw
+6 1 0:24 / /mnt/a/b rw - tmpfs b rw
+7 1 0:25 / /mnt rw - tmpfs mnt rw
+8 2 0:26 / /var rw - tmpfs var2 rw",
+        ));
+        let diff = old.diff(&new);
+        assert!(diff.changed.is_empty(), "{:?}", diff.changed);
+        assert_eq!(diff.unmounted.len(), 1);
+        assert_eq!(ids(diff.unmounted[0].iter()), [3, 4, 5]);
+        assert_eq!(
+            diff.unmounted[0].root.mount_point,
+            Path::new("/var/lib/containers/x")
+        );
+        assert_eq!(diff.mounted.len(), 1);
+        assert_eq!(
+            mount_points(diff.mounted[0].iter()),
+            ["/var/lib/containers/y", "/var/lib/containers/y/data"].map(PathBuf::from)
+        );
+    }
+}
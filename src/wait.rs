@@ -0,0 +1,278 @@
+//! Wait for a filesystem to be mounted or unmounted.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use mount_watcher::wait_for_mount;
+//!
+//! // block until a filesystem is mounted at /mnt/backup, for at most one minute
+//! let mount = wait_for_mount("/mnt/backup", |_| true, Duration::from_secs(60)).unwrap();
+//! println!("backup disk mounted: {mount:?}");
+//! ```
+
+use std::{
+    any::Any,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use thiserror::Error;
+
+use crate::{
+    mount::LinuxMount,
+    watch::{payload_message, JoinError, SetupError, WatchError},
+    MountEvent, MountWatcher, WatchControl,
+};
+
+/// Error returned by [`wait_for_mount`] and [`wait_for_unmount`].
+#[derive(Debug, Error)]
+pub enum WaitError {
+    /// The timeout has elapsed before the condition was satisfied.
+    #[error("timed out while waiting for the mount condition")]
+    Timeout,
+    /// The watcher could not be started.
+    #[error("failed to start the watcher")]
+    Setup(#[source] SetupError),
+    /// The watcher has stopped because of an error.
+    #[error("the watcher has failed")]
+    Watch(#[source] WatchError),
+    /// The watcher has stopped without error, before the condition was satisfied.
+    #[error("the watcher has stopped before the mount condition was satisfied")]
+    Stopped,
+    /// The background thread of the watcher has panicked, with the given payload.
+    #[error("the watcher thread has panicked: {}", payload_message(.0.as_ref()).unwrap_or("unknown panic payload"))]
+    Panicked(Box<dyn Any + Send>),
+}
+
+impl From<JoinError> for WaitError {
+    fn from(e: JoinError) -> Self {
+        match e {
+            JoinError::Watch(e) => WaitError::Watch(e),
+            JoinError::Panicked(payload) => WaitError::Panicked(payload),
+        }
+    }
+}
+
+/// Blocks until a filesystem that satisfies the `predicate` is mounted at `path`,
+/// and returns it.
+///
+/// If such a filesystem is already mounted, this returns immediately.
+/// A remount that makes an existing mount satisfy the `predicate` also ends the wait.
+///
+/// The `path` must be written like in `/proc/mounts`: absolute, without symbolic links.
+pub fn wait_for_mount(
+    path: impl AsRef<Path>,
+    predicate: impl FnMut(&LinuxMount) -> bool + Send + 'static,
+    timeout: Duration,
+) -> Result<LinuxMount, WaitError> {
+    let mut condition = MountCondition::new(path.as_ref(), predicate);
+    let (tx, rx) = mpsc::sync_channel(1);
+    let watch = MountWatcher::new(move |event| match condition.find_mounted(event) {
+        Some(mount) => {
+            let _ = tx.send(mount);
+            WatchControl::Stop
+        }
+        None => WatchControl::Continue,
+    })
+    .map_err(WaitError::Setup)?;
+    wait(watch, rx, timeout)
+}
+
+/// Blocks until no filesystem that satisfies the `predicate` is mounted at `path`.
+///
+/// If there is no such filesystem, this returns immediately.
+/// When several filesystems are stacked on `path`, this waits until they are all unmounted.
+///
+/// The `path` must be written like in `/proc/mounts`: absolute, without symbolic links.
+pub fn wait_for_unmount(
+    path: impl AsRef<Path>,
+    predicate: impl FnMut(&LinuxMount) -> bool + Send + 'static,
+    timeout: Duration,
+) -> Result<(), WaitError> {
+    let mut condition = MountCondition::new(path.as_ref(), predicate);
+    let mut remaining = HashSet::new();
+    let (tx, rx) = mpsc::sync_channel(1);
+    let watch = MountWatcher::new(move |event| {
+        if condition.all_unmounted(event, &mut remaining) {
+            let _ = tx.send(());
+            WatchControl::Stop
+        } else {
+            WatchControl::Continue
+        }
+    })
+    .map_err(WaitError::Setup)?;
+    wait(watch, rx, timeout)
+}
+
+fn wait<T>(watch: MountWatcher, rx: Receiver<T>, timeout: Duration) -> Result<T, WaitError> {
+    match rx.recv_timeout(timeout) {
+        Ok(res) => Ok(res),
+        Err(RecvTimeoutError::Timeout) => Err(WaitError::Timeout), // the watcher stops on drop
+        Err(RecvTimeoutError::Disconnected) => {
+            // The callback has been dropped without sending anything: the watcher has failed.
+            watch.join()?;
+            Err(WaitError::Stopped)
+        }
+    }
+}
+
+/// Async version of [`wait_for_mount`], based on [`MountWatcher::stream`].
+///
+/// # Panics
+/// This function panics if it is not called from within a tokio runtime,
+/// with IO and time enabled.
+#[cfg(feature = "tokio")]
+pub async fn wait_for_mount_async(
+    path: impl AsRef<Path>,
+    predicate: impl FnMut(&LinuxMount) -> bool,
+    timeout: Duration,
+) -> Result<LinuxMount, WaitError> {
+    let mut condition = MountCondition::new(path.as_ref(), predicate);
+    let stream = MountWatcher::stream().map_err(WaitError::Setup)?;
+    let wait = next_matching(stream, |event| condition.find_mounted(event));
+    tokio::time::timeout(timeout, wait)
+        .await
+        .unwrap_or(Err(WaitError::Timeout))
+}
+
+/// Async version of [`wait_for_unmount`], based on [`MountWatcher::stream`].
+///
+/// # Panics
+/// This function panics if it is not called from within a tokio runtime,
+/// with IO and time enabled.
+#[cfg(feature = "tokio")]
+pub async fn wait_for_unmount_async(
+    path: impl AsRef<Path>,
+    predicate: impl FnMut(&LinuxMount) -> bool,
+    timeout: Duration,
+) -> Result<(), WaitError> {
+    let mut condition = MountCondition::new(path.as_ref(), predicate);
+    let mut remaining = HashSet::new();
+    let stream = MountWatcher::stream().map_err(WaitError::Setup)?;
+    let wait = next_matching(stream, |event| {
+        condition.all_unmounted(event, &mut remaining).then_some(())
+    });
+    tokio::time::timeout(timeout, wait)
+        .await
+        .unwrap_or(Err(WaitError::Timeout))
+}
+
+/// Polls the `stream` until `f` returns something.
+#[cfg(feature = "tokio")]
+async fn next_matching<T>(
+    mut stream: crate::stream::MountStream,
+    mut f: impl FnMut(MountEvent) -> Option<T>,
+) -> Result<T, WaitError> {
+    use futures_core::Stream;
+    use std::pin::Pin;
+
+    while let Some(item) = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+        let event = item.map_err(|e| WaitError::Watch(WatchError(e.into())))?;
+        if let Some(res) = f(event) {
+            return Ok(res);
+        }
+    }
+    Err(WaitError::Stopped)
+}
+
+/// The mounts that we are waiting for.
+struct MountCondition<P> {
+    path: PathBuf,
+    predicate: P,
+}
+
+impl<P: FnMut(&LinuxMount) -> bool> MountCondition<P> {
+    fn new(path: &Path, predicate: P) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            predicate,
+        }
+    }
+
+    fn matches(&mut self, mount: &LinuxMount) -> bool {
+        mount.mount_point == self.path && (self.predicate)(mount)
+    }
+
+    /// Returns the first mount of the event that satisfies the condition.
+    fn find_mounted(&mut self, event: MountEvent) -> Option<LinuxMount> {
+        let changed = event.changed.into_iter().map(|c| c.new);
+        event
+            .mounted
+            .into_iter()
+            .chain(changed)
+            .find(|m| self.matches(m))
+    }
+
+    /// Updates the set of the `remaining` mounts that satisfy the condition,
+    /// and returns `true` if it is empty.
+    fn all_unmounted(&mut self, event: MountEvent, remaining: &mut HashSet<LinuxMount>) -> bool {
+        for mount in event.unmounted {
+            remaining.remove(&mount);
+        }
+        for change in event.changed {
+            remaining.remove(&change.old);
+            if self.matches(&change.new) {
+                remaining.insert(change.new);
+            }
+        }
+        for mount in event.mounted {
+            if self.matches(&mount) {
+                remaining.insert(mount);
+            }
+        }
+        remaining.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use super::{wait_for_mount, wait_for_unmount, WaitError};
+
+    #[test]
+    fn already_mounted() {
+        let mount =
+            wait_for_mount("/proc", |m| m.fs_type == "proc", Duration::from_secs(5)).unwrap();
+        assert_eq!(mount.mount_point, Path::new("/proc"));
+    }
+
+    #[test]
+    fn mount_timeout() {
+        let res = wait_for_mount(
+            "/proc",
+            |m| m.fs_type == "nonexistent-fs",
+            Duration::from_millis(100),
+        );
+        assert!(matches!(res, Err(WaitError::Timeout)), "{res:?}");
+    }
+
+    #[test]
+    fn already_unmounted() {
+        wait_for_unmount("/nonexistent", |_| true, Duration::from_secs(5)).unwrap();
+        let res = wait_for_unmount("/proc", |_| true, Duration::from_millis(100));
+        assert!(matches!(res, Err(WaitError::Timeout)), "{res:?}");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_wait() {
+        use super::{wait_for_mount_async, wait_for_unmount_async};
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mount = wait_for_mount_async("/proc", |_| true, Duration::from_secs(5))
+                .await
+                .unwrap();
+            assert_eq!(mount.fs_type, "proc");
+            let res = wait_for_unmount_async("/proc", |_| true, Duration::from_millis(100)).await;
+            assert!(matches!(res, Err(WaitError::Timeout)), "{res:?}");
+        });
+    }
+}
@@ -1,17 +1,29 @@
 //! Main module.
 
 use std::{
-    collections::HashSet, fs::File, io::ErrorKind, os::fd::AsRawFd, sync::Arc, thread::JoinHandle,
+    any::Any,
+    collections::HashSet,
+    fs::File,
+    io::ErrorKind,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
     time::Duration,
 };
 
-use mio::{unix::SourceFd, Events, Interest, Poll, Token, Waker};
+use mio::{unix::SourceFd, Events, Interest, Poll, Registry, Token, Waker};
 use thiserror::Error;
 use timerfd::TimerFd;
 
-use crate::mount::ReadError;
-
-use super::mount::{read_proc_mounts, LinuxMount};
+use crate::builder::{ErrorHook, MountWatcherBuilder, TableLocation};
+use crate::filter::MountFilter;
+use crate::fstab::{FstabEvent, FstabWatch, FSTAB_DELAY};
+use crate::mount::{read_mount_table, LinuxMount, MountEntry, OnInvalidLine, ReadError};
+use crate::mountinfo::{MountInfo, PROC_MOUNTINFO_PATH};
+use crate::options::OptionsDiff;
+use crate::table::{register_source, MountTableSource, ProcMountTable, TableChanges};
 
 /// `MountWatcher` allows to react to changes in the mounted filesystems.
 ///
@@ -42,24 +54,127 @@ use super::mount::{read_proc_mounts, LinuxMount};
 /// // Wait for the watcher to be stopped by the handler
 /// watch.join().unwrap();
 /// ```
-pub struct MountWatcher {
-    thread_handle: Option<JoinHandle<()>>,
+///
+/// # Errors
+///
+/// If the background thread encounters an error (for instance, the mount table cannot be read),
+/// it stops and the error is returned by [`join`](Self::join).
+/// To keep watching despite read errors, or to be notified as soon as an error occurs,
+/// see [`MountWatcherBuilder::error_policy`] and [`MountWatcherBuilder::on_error`].
+///
+/// When watching another process with [`MountWatcherBuilder::pid`], the watcher also stops
+/// when this process exits, see [`WatchError::exited_pid`].
+pub struct MountWatcher<M = LinuxMount> {
+    thread_handle: Option<JoinHandle<Result<(), WatchError>>>,
     stop_waker: Arc<Waker>,
+    snapshot: Arc<Mutex<MountSnapshot<M>>>,
 }
 
 /// Error in `MountWatcher` setup.
 #[derive(Debug, Error)]
 #[error("MountWatcher setup error")]
-pub struct SetupError(#[source] ErrorImpl);
+pub struct SetupError(#[source] pub(crate) ErrorImpl);
 
 /// Error in [`MountWatcher::stop`].
 #[derive(Debug, Error)]
 #[error("MountWatcher stop error")]
-pub struct StopError(#[source] ErrorImpl);
+pub struct StopError(#[source] pub(crate) ErrorImpl);
+
+/// Error that occurred in the background thread of a `MountWatcher`.
+#[derive(Debug, Error)]
+#[error("MountWatcher polling loop error")]
+pub struct WatchError(#[source] pub(crate) ErrorImpl);
+
+impl WatchError {
+    /// Returns the underlying error if it was caused by a failed read of the mount table.
+    pub fn as_read_error(&self) -> Option<&ReadError> {
+        match &self.0 {
+            ErrorImpl::MountRead(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Returns the error returned by the callback, if it is the cause of this error.
+    ///
+    /// Use `downcast_ref` to get your own error type.
+    pub fn as_callback_error(&self) -> Option<&(dyn std::error::Error + Send + Sync + 'static)> {
+        match &self.0 {
+            ErrorImpl::Callback(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// Returns the panic message if this error comes from a panic of the callback.
+    ///
+    /// See [`MountWatcherBuilder::catch_panics`].
+    pub fn as_panic_message(&self) -> Option<&str> {
+        match &self.0 {
+            ErrorImpl::CallbackPanic(msg) => Some(msg),
+            _ => None,
+        }
+    }
+
+    /// Returns the pid of the watched process if the watcher has stopped because it has exited.
+    ///
+    /// See [`MountWatcherBuilder::pid`].
+    pub fn exited_pid(&self) -> Option<u32> {
+        match &self.0 {
+            ErrorImpl::ProcessExited(pid) => Some(*pid),
+            _ => None,
+        }
+    }
+}
+
+/// Error in [`MountWatcher::join`].
+#[derive(Debug, Error)]
+pub enum JoinError {
+    /// The polling loop has stopped because of an error.
+    #[error("MountWatcher polling loop has failed")]
+    Watch(#[from] WatchError),
+    /// The background thread has panicked, with the given payload.
+    ///
+    /// The payload can be given to [`std::panic::resume_unwind`].
+    #[error("MountWatcher thread has panicked: {}", payload_message(.0.as_ref()).unwrap_or("unknown panic payload"))]
+    Panicked(Box<dyn Any + Send>),
+}
+
+impl JoinError {
+    /// Returns the panic message if the background thread has panicked with a string,
+    /// as `panic!` does.
+    pub fn panic_message(&self) -> Option<&str> {
+        match self {
+            JoinError::Panicked(payload) => payload_message(payload.as_ref()),
+            JoinError::Watch(_) => None,
+        }
+    }
+}
+
+/// What to do when the mount table cannot be read, or when the callback returns an error
+/// (or panics, see [`MountWatcherBuilder::catch_panics`]).
+///
+/// The other errors (e.g. `epoll` failures) always stop the watcher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Stop the watcher, the error is returned by [`MountWatcher::join`].
+    #[default]
+    Stop,
+    /// Ignore the failed read, or the event that the callback has failed to handle,
+    /// and wait for the next change.
+    Skip,
+    /// Read the mount table again after a delay, which is doubled after each
+    /// consecutive failure, up to `max_delay`.
+    ///
+    /// If the callback has failed, it will get the changes again, in a new event
+    /// that also contains the changes that occurred during the delay.
+    Retry {
+        initial_delay: Duration,
+        max_delay: Duration,
+    },
+}
 
 /// Private error type: I don't want to expose it for the moment.
 #[derive(Debug, Error)]
-enum ErrorImpl {
+pub(crate) enum ErrorImpl {
     #[error("read error")]
     MountRead(#[from] ReadError),
     #[error("failed to initialize epoll")]
@@ -70,16 +185,105 @@ enum ErrorImpl {
     PollTimer(#[source] std::io::Error),
     #[error("could not set up a timer with delay {0:?} for event coalescing")]
     Timerfd(Duration, #[source] std::io::Error),
+    #[error("failed to create the timer for event coalescing")]
+    TimerInit(#[source] std::io::Error),
     #[error("failed to stop epoll from another thread")]
     Stop(#[source] std::io::Error),
+    #[error("failed to spawn the background thread")]
+    ThreadSpawn(#[source] std::io::Error),
+    #[error("the callback has returned an error")]
+    Callback(#[source] CallbackError),
+    #[error("the callback has panicked: {0}")]
+    CallbackPanic(String),
+    #[error("failed to get a pidfd for process {0}")]
+    Pidfd(u32, #[source] std::io::Error),
+    #[error("the watched process {0} has exited")]
+    ProcessExited(u32),
+    #[error("the MountWatchHub has stopped")]
+    HubStopped,
+    #[error("cannot wait for the MountWatchHub from one of its callbacks")]
+    HubThread,
+    #[error("the MountWatchHub cannot watch fstab, use a MountWatcher")]
+    HubFstab,
+    #[error("failed to resolve the path {0:?}")]
+    BackingPath(PathBuf, #[source] std::io::Error),
+    #[error("failed to read the fstab file {0:?}")]
+    FstabRead(PathBuf, #[source] ReadError),
+    #[error("failed to watch the fstab file with inotify")]
+    Inotify(#[source] std::io::Error),
 }
 
+/// Error returned by a fallible callback, see [`MountWatcher::try_new`].
+pub type CallbackError = Box<dyn std::error::Error + Send + Sync>;
+
 impl MountWatcher {
     /// Watches the list of mounted filesystems and executes the `callback` when it changes.
+    ///
+    /// To configure the watcher, use [`builder`](Self::builder).
     pub fn new(
         callback: impl FnMut(MountEvent) -> WatchControl + Send + 'static,
     ) -> Result<Self, SetupError> {
-        watch_mounts(callback).map_err(SetupError)
+        MountWatcherBuilder::new().build(callback)
+    }
+
+    /// Like [`new`](Self::new), with a callback that can fail.
+    ///
+    /// When the callback returns an error, the watcher stops and the error is returned by
+    /// [`join`](Self::join), unless another [`ErrorPolicy`] is configured with the
+    /// [`builder`](Self::builder).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use mount_watcher::{MountWatcher, WatchControl};
+    ///
+    /// let watch = MountWatcher::try_new(|event| {
+    ///     for mount in event.mounted {
+    ///         std::fs::metadata(&mount.mount_point)?;
+    ///     }
+    ///     Ok::<_, std::io::Error>(WatchControl::Continue)
+    /// }).unwrap();
+    /// ```
+    pub fn try_new<E: Into<CallbackError>>(
+        callback: impl FnMut(MountEvent) -> Result<WatchControl, E> + Send + 'static,
+    ) -> Result<Self, SetupError> {
+        MountWatcherBuilder::new().try_build(callback)
+    }
+
+    /// Watches the mounted filesystems that match the `filter`, and executes the `callback` when they change.
+    ///
+    /// The changes that only involve filtered-out mounts are ignored: they don't trigger
+    /// the callback, and they don't start any coalescing.
+    pub fn with_filter(
+        filter: MountFilter,
+        callback: impl FnMut(MountEvent) -> WatchControl + Send + 'static,
+    ) -> Result<Self, SetupError> {
+        MountWatcherBuilder::new().filter(filter).build(callback)
+    }
+
+    /// Watches `/proc/self/mountinfo` and executes the `callback` when it changes.
+    ///
+    /// Unlike [`new`](Self::new), the events contain [`MountInfo`] entries, which include
+    /// the mount ID, the parent ID and the propagation tags of each mount.
+    /// Two mounts that look identical in `/proc/mounts` (e.g. two bind mounts of the same
+    /// directory at the same place) are thus reported separately.
+    pub fn new_mountinfo(
+        callback: impl FnMut(MountEvent<MountInfo>) -> WatchControl + Send + 'static,
+    ) -> Result<MountWatcher<MountInfo>, SetupError> {
+        MountWatcherBuilder::new().mountinfo().build(callback)
+    }
+}
+
+impl<M> MountWatcher<M> {
+    /// Returns the mounts that are known by the watcher.
+    ///
+    /// The snapshot is updated after each call of the callback (except when it returns
+    /// [`WatchControl::Coalesce`]), see [`MountSnapshot::generation`].
+    ///
+    /// Note that the snapshot contains all the mounts, including those that
+    /// don't match the filter of the watcher.
+    pub fn snapshot(&self) -> MountSnapshot<M> {
+        self.snapshot.lock().unwrap().clone()
     }
 
     /// Requests the background thread to terminate.
@@ -96,13 +300,25 @@ impl MountWatcher {
     /// This blocks the current thread.
     ///
     /// # Errors
-    /// If the background thread has panicked, an error is returned with the panic payload.
-    pub fn join(mut self) -> std::thread::Result<()> {
-        self.thread_handle.take().unwrap().join()
+    /// If the polling loop has stopped because of an error, or if the background thread
+    /// has panicked, an error is returned.
+    pub fn join(mut self) -> Result<(), JoinError> {
+        match self.thread_handle.take().unwrap().join() {
+            Ok(res) => Ok(res?),
+            Err(payload) => Err(JoinError::Panicked(payload)),
+        }
+    }
+}
+
+/// Extracts the message of a panic, which is usually a `&str` or a `String`.
+pub(crate) fn payload_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    match payload.downcast_ref::<String>() {
+        Some(msg) => Some(msg),
+        None => payload.downcast_ref::<&str>().copied(),
     }
 }
 
-impl Drop for MountWatcher {
+impl<M> Drop for MountWatcher<M> {
     fn drop(&mut self) {
         if self.thread_handle.is_some() {
             let _ = self.stop();
@@ -111,12 +327,24 @@ impl Drop for MountWatcher {
 }
 
 /// Event generated when the mounted filesystems change.
-pub struct MountEvent {
+///
+/// By default, the mounts are described by [`LinuxMount`] (i.e. lines of `/proc/mounts`).
+/// With [`MountWatcher::new_mountinfo`], they are described by [`MountInfo`].
+pub struct MountEvent<M = LinuxMount> {
     /// The new filesystems that have been mounted.
-    pub mounted: Vec<LinuxMount>,
+    pub mounted: Vec<M>,
 
     /// The old filesystems that have been unmounted.
-    pub unmounted: Vec<LinuxMount>,
+    pub unmounted: Vec<M>,
+
+    /// The filesystems that are still mounted, but whose description has changed,
+    /// for instance after `mount -o remount,ro`.
+    ///
+    /// The mounts are matched with [`MountEntry::is_same_mount`]: by mount point and
+    /// source for [`LinuxMount`], by mount ID, device, root and mount point for [`MountInfo`].
+    /// With [`MountWatcherBuilder::backing_mount_of`], this also reports the replacement
+    /// of the watched mount by a filesystem mounted over it.
+    pub changed: Vec<MountChange<M>>,
 
     /// Indicates whether this is a coalesced event.
     ///
@@ -125,7 +353,89 @@ pub struct MountEvent {
 
     /// Indicates whether this is the first event, which contains
     /// the list of all the mounts.
+    ///
+    /// The initial event is always sent, even if it is empty
+    /// (e.g. because no mount matches the filter).
     pub initial: bool,
+
+    /// The generation of the [`MountSnapshot`] that includes the changes of this event.
+    pub generation: u64,
+
+    /// The changes of fstab, with [`MountWatcherBuilder::watch_fstab`].
+    ///
+    /// When fstab is modified, the watcher reads it with the mount table: the event may
+    /// contain no other change.
+    pub fstab: Option<FstabEvent>,
+}
+
+impl<M> MountEvent<M> {
+    /// Returns `true` if the event contains no mount, no unmount, no change and no fstab change.
+    ///
+    /// Only the initial event can be empty.
+    pub fn is_empty(&self) -> bool {
+        self.mounted.is_empty()
+            && self.unmounted.is_empty()
+            && self.changed.is_empty()
+            && self.fstab.is_none()
+    }
+}
+
+/// A mount whose description has changed, see [`MountEvent::changed`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MountChange<M = LinuxMount> {
+    /// The mount before the change.
+    pub old: M,
+    /// The mount after the change.
+    pub new: M,
+}
+
+impl<M: MountEntry> MountChange<M> {
+    /// Lists the mount options that have changed, for instance `rw` to `ro` after a remount.
+    pub fn options_diff(&self) -> OptionsDiff<'_> {
+        self.old.options().diff(&self.new.options())
+    }
+}
+
+/// The mounts that are known by a [`MountWatcher`], see [`MountWatcher::snapshot`].
+///
+/// Cloning a snapshot is cheap: the mounts are shared.
+#[derive(Debug)]
+pub struct MountSnapshot<M = LinuxMount> {
+    mounts: Arc<HashSet<M>>,
+    generation: u64,
+}
+
+impl<M> Clone for MountSnapshot<M> {
+    fn clone(&self) -> Self {
+        Self {
+            mounts: Arc::clone(&self.mounts),
+            generation: self.generation,
+        }
+    }
+}
+
+impl<M> MountSnapshot<M> {
+    pub(crate) fn empty() -> Self {
+        Self {
+            mounts: Arc::new(HashSet::new()),
+            generation: 0,
+        }
+    }
+
+    /// Returns the mounts.
+    pub fn mounts(&self) -> &HashSet<M> {
+        &self.mounts
+    }
+
+    /// Returns the generation of the snapshot, which is incremented each time the known
+    /// mounts are updated.
+    ///
+    /// The generation is 0 until the mount table has been read for the first time.
+    /// Once you have handled a [`MountEvent`], the snapshots with a generation greater or equal
+    /// to [`MountEvent::generation`] include its changes.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
 }
 
 /// Value returned by the event handler to control the [`MountWatcher`].
@@ -144,120 +454,599 @@ pub enum WatchControl {
 const MOUNT_TOKEN: Token = Token(0);
 const TIMER_TOKEN: Token = Token(1);
 const STOP_TOKEN: Token = Token(2);
-const POLL_TIMEOUT: Duration = Duration::from_secs(5);
-const PROC_MOUNTS_PATH: &str = "/proc/mounts";
+const RETRY_TOKEN: Token = Token(3);
+const PROCESS_TOKEN: Token = Token(4);
+const FSTAB_TOKEN: Token = Token(5);
+pub(crate) const POLL_TIMEOUT: Duration = Duration::from_secs(5);
 
-struct State<F: FnMut(MountEvent) -> WatchControl> {
-    known_mounts: HashSet<LinuxMount>,
+/// What has woken the polling loop up, for a [`State`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Trigger {
+    /// The mount table has changed.
+    Mount,
+    /// The coalescing delay has elapsed.
+    Timer,
+    /// The retry delay has elapsed.
+    Retry,
+}
+
+/// The options of the builder that are used by [`State`].
+pub(crate) struct StateOptions {
+    pub(crate) filter: Option<MountFilter>,
+    pub(crate) backing_path: Option<BackingPath>,
+    pub(crate) initial_event: bool,
+    pub(crate) error_policy: ErrorPolicy,
+    pub(crate) error_hook: Option<ErrorHook>,
+    pub(crate) catch_panics: bool,
+    pub(crate) fstab: Option<FstabWatch>,
+}
+
+/// The path whose mount is watched, see [`MountWatcherBuilder::backing_mount_of`].
+pub(crate) struct BackingPath {
+    /// Canonical path.
+    path: PathBuf,
+    /// The mountinfo table that describes the same mounts as the watched table, if known.
+    mountinfo: Option<PathBuf>,
+}
+
+impl BackingPath {
+    /// Returns the mount that contains the path, among the `mounts` of the watched table.
+    fn find<M: MountEntry>(&self, mounts: &[M]) -> Result<Option<M>, ReadError> {
+        let mount = match &self.mountinfo {
+            Some(table) => {
+                let infos = read_mount_table(&mut File::open(table)?, OnInvalidLine::Skip)?;
+                M::find_for_path_with_mountinfo(mounts, &infos, &self.path)
+            }
+            None => M::find_for_path(mounts, &self.path),
+        };
+        Ok(mount.cloned())
+    }
+}
+
+/// Canonicalizes the path given to [`MountWatcherBuilder::backing_mount_of`], and finds the
+/// mountinfo table of the watched table, unless it comes from a custom source.
+pub(crate) fn resolve_backing_path<M: MountEntry>(
+    path: Option<PathBuf>,
+    location: Option<&TableLocation>,
+    has_source: bool,
+) -> Result<Option<BackingPath>, ErrorImpl> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let path = path
+        .canonicalize()
+        .map_err(|e| ErrorImpl::BackingPath(path, e))?;
+    let mountinfo = match location {
+        // MountInfo entries already tell how the mounts are stacked.
+        _ if has_source || M::TABLE_PATH == PROC_MOUNTINFO_PATH => None,
+        None => Some(PathBuf::from(PROC_MOUNTINFO_PATH)),
+        Some(TableLocation::Pid(pid)) => Some(PathBuf::from(format!("/proc/{pid}/mountinfo"))),
+        Some(TableLocation::Path(_) | TableLocation::Namespace(_)) => None,
+    };
+    Ok(Some(BackingPath { path, mountinfo }))
+}
+
+/// The tokens of the timers of a [`State`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TimerTokens {
+    pub(crate) coalesce: Token,
+    pub(crate) retry: Token,
+}
+
+/// Reads the mounts for [`State::handle_event`], like a [`MountTableSource`].
+pub(crate) trait ReadMounts<M> {
+    /// Reads the current mounts.
+    fn read(&mut self) -> Result<Vec<M>, ReadError>;
+
+    /// Reads the changes since the previous read, see [`MountTableSource::read_changes`].
+    fn read_changes(&mut self) -> Result<Option<TableChanges<M>>, ReadError>;
+}
+
+impl<M: 'static> ReadMounts<M> for Box<dyn MountTableSource<M>> {
+    fn read(&mut self) -> Result<Vec<M>, ReadError> {
+        MountTableSource::read(self.as_mut())
+    }
+
+    fn read_changes(&mut self) -> Result<Option<TableChanges<M>>, ReadError> {
+        MountTableSource::read_changes(self.as_mut())
+    }
+}
+
+/// State of a callback, which keeps track of the mounts that it knows.
+pub(crate) struct State<
+    M: MountEntry,
+    F: FnMut(MountEvent<M>) -> Result<WatchControl, CallbackError>,
+> {
+    known_mounts: MountSnapshot<M>,
+    /// Copy of `known_mounts`, shared with the `MountWatcher`.
+    shared_snapshot: Arc<Mutex<MountSnapshot<M>>>,
+    filter: Option<MountFilter>,
+    backing_path: Option<BackingPath>,
+    initial_event: bool,
+    /// Set after the initial event has been handled.
+    initial_done: bool,
     callback: F,
+    error_policy: ErrorPolicy,
+    error_hook: Option<ErrorHook>,
+    catch_panics: bool,
+    tokens: TimerTokens,
     coalesce_timer: Option<TimerFd>,
     coalescing: bool,
+    /// Set when the coalescing timer waits for the end of a modification of fstab, rather
+    /// than for the delay requested by the callback.
+    fstab_wait: bool,
+    retry_timer: Option<TimerFd>,
+    /// Set when a read has failed and will be retried.
+    retry: Option<Retry>,
+    fstab: Option<FstabWatch>,
+    /// The changes read from the source since `known_mounts`, or `None` if the next read
+    /// must compare the whole table, because the source does not know its changes or
+    /// because the mounts that have been read are not committed.
+    pending: Option<TableChanges<M>>,
 }
 
-impl<F: FnMut(MountEvent) -> WatchControl> State<F> {
-    fn new(callback: F) -> Self {
+#[derive(Clone, Copy)]
+struct Retry {
+    delay: Duration,
+    coalesced: bool,
+}
+
+impl<M: MountEntry, F: FnMut(MountEvent<M>) -> Result<WatchControl, CallbackError>> State<M, F> {
+    pub(crate) fn new(
+        options: StateOptions,
+        tokens: TimerTokens,
+        shared_snapshot: Arc<Mutex<MountSnapshot<M>>>,
+        callback: F,
+    ) -> Self {
         Self {
-            known_mounts: HashSet::with_capacity(8),
+            known_mounts: MountSnapshot::empty(),
+            shared_snapshot,
+            filter: options.filter,
+            backing_path: options.backing_path,
+            initial_event: options.initial_event,
+            initial_done: false,
             callback,
+            error_policy: options.error_policy,
+            error_hook: options.error_hook,
+            catch_panics: options.catch_panics,
+            tokens,
             coalesce_timer: None,
             coalescing: false,
+            fstab_wait: false,
+            retry_timer: None,
+            retry: None,
+            fstab: options.fstab,
+            pending: None,
+        }
+    }
+
+    /// Handles an event of the poll loop, with `read` to get the current mounts.
+    ///
+    /// Returns `false` if the watcher must stop.
+    pub(crate) fn handle_event(
+        &mut self,
+        trigger: Trigger,
+        read: &mut dyn ReadMounts<M>,
+        poll: &Poll,
+    ) -> Result<bool, ErrorImpl> {
+        let coalesced = match trigger {
+            Trigger::Mount if self.coalescing || self.retry.is_some() => {
+                // We are waiting for a timer, which will read the mount table anyway.
+                return Ok(true);
+            }
+            Trigger::Mount => false,
+            Trigger::Timer => !std::mem::take(&mut self.fstab_wait),
+            Trigger::Retry => match self.retry {
+                Some(retry) => retry.coalesced,
+                None => return Ok(true),
+            },
+        };
+
+        match self.check_diff(read, coalesced) {
+            Ok(res) => {
+                self.retry = None;
+                self.initial_done = true;
+                // If a timer has been triggered, the event has been handled: clear the flag.
+                self.coalescing = false;
+                match res {
+                    WatchControl::Continue => Ok(true),
+                    WatchControl::Stop => Ok(false),
+                    WatchControl::Coalesce { delay } => {
+                        self.start_coalescing(delay, poll)?;
+                        Ok(true)
+                    }
+                }
+            }
+            Err(e) => self.handle_error(e, coalesced, poll),
+        }
+    }
+
+    /// Handles a notification of the fstab watch: fstab will be read with the mount table
+    /// after a short delay, because editors often write the file in several steps.
+    pub(crate) fn handle_fstab(&mut self, poll: &Poll) -> Result<(), ErrorImpl> {
+        let Some(fstab) = &mut self.fstab else {
+            return Ok(());
+        };
+        // Otherwise, we are already waiting for a timer, which will read fstab anyway.
+        if fstab.read_notifications()? && !self.coalescing && self.retry.is_none() {
+            log::trace!("fstab modified, reading it in {FSTAB_DELAY:?}");
+            self.start_coalescing(FSTAB_DELAY, poll)?;
+            self.fstab_wait = true;
+        }
+        Ok(())
+    }
+
+    fn handle_error(
+        &mut self,
+        error: ErrorImpl,
+        coalesced: bool,
+        poll: &Poll,
+    ) -> Result<bool, ErrorImpl> {
+        let error = WatchError(error);
+        if let Some(hook) = &mut self.error_hook {
+            (hook.0)(&error);
+        }
+        match self.error_policy {
+            ErrorPolicy::Stop => Err(error.0),
+            ErrorPolicy::Skip => {
+                log::warn!("skipping error: {error:?}");
+                self.coalescing = false;
+                Ok(true)
+            }
+            ErrorPolicy::Retry {
+                initial_delay,
+                max_delay,
+            } => {
+                let delay = match self.retry {
+                    Some(retry) => (retry.delay * 2).min(max_delay),
+                    None => initial_delay,
+                };
+                log::warn!("retrying in {delay:?} after error: {error:?}");
+                arm_timer(&mut self.retry_timer, self.tokens.retry, delay, poll)?;
+                self.retry = Some(Retry { delay, coalesced });
+                Ok(true)
+            }
         }
     }
 
     fn check_diff(
         &mut self,
-        file: &mut File,
+        read: &mut dyn ReadMounts<M>,
         coalesced: bool,
-        initial: bool,
-    ) -> Result<WatchControl, ReadError> {
+    ) -> Result<WatchControl, ErrorImpl> {
         debug_assert!(
-            !(coalesced && !self.coalescing),
+            !coalesced || self.coalescing,
             "inconsistent state: coalescing flag should be set before setting the trigger up"
         );
-        if self.coalescing {
-            if coalesced {
-                // The timer has been triggered, clear the flag.
-                self.coalescing = false;
-            } else {
-                // We are coalescing the events, wait for the timer.
-                return Ok(WatchControl::Continue);
+
+        // The backing mount is searched in the whole table, the changes are not enough.
+        let changes = match &self.pending {
+            Some(_) if self.backing_path.is_none() => read.read_changes()?,
+            _ => None,
+        };
+        let (mounts, changes, replaced) = match changes {
+            Some(changes) => {
+                // Don't compare the whole table, apply the changes to the known mounts.
+                let pending = self.pending.get_or_insert_with(TableChanges::default);
+                pending.merge(changes);
+                let mut mounts = HashSet::clone(&self.known_mounts.mounts);
+                for mount in &pending.unmounted {
+                    mounts.remove(mount);
+                }
+                mounts.extend(pending.mounted.iter().cloned());
+                (mounts, Some(pending.clone()), None)
+            }
+            None => {
+                self.pending = None;
+                let mounts = read.read()?;
+                match &self.backing_path {
+                    Some(backing) => {
+                        let current = backing.find(&mounts)?;
+                        // The previous mount is still mounted, but the path is now on another one.
+                        let replaced = self
+                            .known_mounts
+                            .mounts
+                            .iter()
+                            .find(|m| Some(*m) != current.as_ref() && mounts.contains(m))
+                            .cloned();
+                        (current.into_iter().collect(), None, replaced)
+                    }
+                    None => (HashSet::from_iter(mounts), None, None),
+                }
             }
+        };
+        let initial = !self.initial_done;
+        if initial && !self.initial_event {
+            // Don't report the initial mounts, only remember them.
+            self.commit(mounts, None);
+            return Ok(WatchControl::Continue);
         }
-
-        let mounts = read_proc_mounts(file)?;
-        let mounts = HashSet::from_iter(mounts);
-        let unmounted: Vec<&LinuxMount> = self.known_mounts.difference(&mounts).collect();
-        let mounted: Vec<&LinuxMount> = mounts.difference(&self.known_mounts).collect();
-        log::trace!("known_mounts: {:?}", self.known_mounts);
-        log::trace!("curr. mounts: {:?}", mounts);
-
-        if mounted.is_empty() && unmounted.is_empty() {
-            // Weird: we got a notification but nothing has changed?
-            // Perhaps something was undone between the moment we got the notification and
-            // the moment we read the /proc/mounts virtual file?
-            log::warn!("nothing changed");
+        let (fstab, fstab_event) = match &mut self.fstab {
+            Some(watch) => watch.read_changes()?.unzip(),
+            None => (None, None),
+        };
+        // A change of fstab is reported even if the mounts have not changed.
+        let event = match changes {
+            Some(changes) if changes.is_empty() && fstab_event.is_none() => None,
+            Some(changes) => Some(MountEvent {
+                mounted: changes.mounted,
+                unmounted: changes.unmounted,
+                changed: Vec::new(),
+                coalesced,
+                initial,
+                generation: 0,
+                fstab: None,
+            }),
+            None => diff_mounts(
+                &self.known_mounts.mounts,
+                &mounts,
+                coalesced,
+                initial || fstab_event.is_some(),
+            ),
+        };
+        let Some(mut event) = event else {
             return Ok(WatchControl::Continue);
+        };
+        event.initial = initial;
+        event.generation = self.known_mounts.generation + 1;
+        event.fstab = fstab_event;
+        if let Some(old) = replaced {
+            report_replaced(&mut event, &old);
+        }
+        if let Some(filter) = &self.filter {
+            filter.apply(&mut event);
+            if event.is_empty() && !initial {
+                // Only filtered-out mounts have changed, don't call the callback.
+                log::trace!("no change after filtering");
+                self.commit(mounts, fstab);
+                return Ok(WatchControl::Continue);
+            }
         }
 
         // call the callback with the changes
-        let event = MountEvent {
-            mounted: mounted.into_iter().cloned().collect(),
-            unmounted: unmounted.into_iter().cloned().collect(),
-            coalesced,
-            initial,
+        let res = if self.catch_panics {
+            match panic::catch_unwind(AssertUnwindSafe(|| (self.callback)(event))) {
+                Ok(res) => res.map_err(ErrorImpl::Callback),
+                Err(payload) => Err(ErrorImpl::CallbackPanic(
+                    payload_message(payload.as_ref())
+                        .unwrap_or("unknown panic payload")
+                        .to_owned(),
+                )),
+            }
+        } else {
+            (self.callback)(event).map_err(ErrorImpl::Callback)
+        };
+        let res = match res {
+            Ok(res) => res,
+            Err(e) => {
+                if self.error_policy == ErrorPolicy::Skip {
+                    // The event is dropped, don't report these changes again.
+                    self.commit(mounts, fstab);
+                    self.initial_done = true;
+                }
+                return Err(e);
+            }
         };
-        let res = (self.callback)(event);
         if !matches!(res, WatchControl::Coalesce { .. }) {
             // When coalescing, don't save the new mounts, we'll compute
             // the difference again and send the future result instead.
             // On the contrary, when NOT coalescing, save the new mounts.
-            self.known_mounts = mounts;
+            self.commit(mounts, fstab);
         }
         // propagate the choice of the callback
         Ok(res)
     }
 
+    /// Saves the new mounts and fstab entries, which have been handled.
+    fn commit(&mut self, mounts: HashSet<M>, fstab: Option<HashSet<LinuxMount>>) {
+        if let (Some(watch), Some(entries)) = (&mut self.fstab, fstab) {
+            watch.commit(entries);
+        }
+        self.pending = Some(TableChanges::default());
+        self.known_mounts = MountSnapshot {
+            mounts: Arc::new(mounts),
+            generation: self.known_mounts.generation + 1,
+        };
+        *self.shared_snapshot.lock().unwrap() = self.known_mounts.clone();
+    }
+
     fn start_coalescing(&mut self, delay: Duration, poll: &Poll) -> Result<(), ErrorImpl> {
         log::trace!("start coalescing for {delay:?}");
-        let mut register = false;
-        if self.coalesce_timer.is_none() {
-            // create the timer, don't register it yet because it is not configured
-            self.coalesce_timer = Some(TimerFd::new().map_err(|e| ErrorImpl::Timerfd(delay, e))?);
-            register = true;
-            log::trace!("timerfd created");
+        arm_timer(&mut self.coalesce_timer, self.tokens.coalesce, delay, poll)?;
+        // set the coalescing flag
+        self.coalescing = true;
+        Ok(())
+    }
+}
+
+/// Configures a oneshot `timer` with the given `delay`.
+///
+/// The timer is created and registered to `poll` on the first call.
+pub(crate) fn arm_timer(
+    timer: &mut Option<TimerFd>,
+    token: Token,
+    delay: Duration,
+    poll: &Poll,
+) -> Result<(), ErrorImpl> {
+    let mut register = false;
+    if timer.is_none() {
+        // create the timer, don't register it yet because it is not configured
+        *timer = Some(TimerFd::new().map_err(|e| ErrorImpl::Timerfd(delay, e))?);
+        register = true;
+        log::trace!("timerfd created");
+    }
+
+    // configure the timer
+    let timer = timer.as_mut().unwrap();
+    timer.set_state(
+        timerfd::TimerState::Oneshot(delay),
+        timerfd::SetTimeFlags::Default,
+    );
+
+    // register the timer to the epoll instance
+    if register {
+        let fd = timer.as_raw_fd();
+        let mut source = SourceFd(&fd);
+        poll.registry()
+            .register(&mut source, token, Interest::READABLE)
+            .map_err(ErrorImpl::PollTimer)?;
+        log::trace!("timerfd registered");
+    }
+    Ok(())
+}
+
+/// Computes the difference between the `known` mounts and the `current` ones.
+///
+/// Returns `None` if nothing has changed, except for the initial event, which is always returned.
+pub(crate) fn diff_mounts<M: MountEntry>(
+    known: &HashSet<M>,
+    current: &HashSet<M>,
+    coalesced: bool,
+    initial: bool,
+) -> Option<MountEvent<M>> {
+    let mut unmounted: Vec<M> = known.difference(current).cloned().collect();
+    let mut mounted: Vec<M> = current.difference(known).cloned().collect();
+    let changed = extract_changes(&mut unmounted, &mut mounted);
+    log::trace!("known_mounts: {:?}", known);
+    log::trace!("curr. mounts: {:?}", current);
+
+    if mounted.is_empty() && unmounted.is_empty() && changed.is_empty() && !initial {
+        // Weird: we got a notification but nothing has changed?
+        // Perhaps something was undone between the moment we got the notification and
+        // the moment we read the mount table?
+        log::warn!("nothing changed");
+        return None;
+    }
+    Some(MountEvent {
+        mounted,
+        unmounted,
+        changed,
+        coalesced,
+        initial,
+        generation: 0, // set by the caller
+        fstab: None,
+    })
+}
+
+/// Finds the mounts that appear in both lists, because they have been modified,
+/// and moves them to a list of changes.
+fn extract_changes<M: MountEntry>(
+    unmounted: &mut Vec<M>,
+    mounted: &mut Vec<M>,
+) -> Vec<MountChange<M>> {
+    let mut changed = Vec::new();
+    let mut i = 0;
+    while i < unmounted.len() {
+        match mounted.iter().position(|m| m.is_same_mount(&unmounted[i])) {
+            Some(j) => changed.push(MountChange {
+                old: unmounted.swap_remove(i),
+                new: mounted.swap_remove(j),
+            }),
+            None => i += 1,
         }
+    }
+    changed
+}
 
-        // configure the timer
-        let timer = self.coalesce_timer.as_mut().unwrap();
-        timer.set_state(
-            timerfd::TimerState::Oneshot(delay),
-            timerfd::SetTimeFlags::Default,
-        );
+/// Reports the replacement of the backing mount as a change instead of an unmount,
+/// because the `old` mount is still mounted.
+fn report_replaced<M: MountEntry>(event: &mut MountEvent<M>, old: &M) {
+    let Some(i) = event.unmounted.iter().position(|m| m == old) else {
+        return;
+    };
+    if event.mounted.len() == 1 {
+        event.changed.push(MountChange {
+            old: event.unmounted.swap_remove(i),
+            new: event.mounted.remove(0),
+        });
+    }
+}
+
+/// Process whose mounts are watched, see [`MountWatcherBuilder::pid`].
+pub(crate) struct WatchedProcess {
+    pub(crate) pid: u32,
+    /// Becomes readable when the process exits.
+    pidfd: OwnedFd,
+}
+
+impl WatchedProcess {
+    pub(crate) fn register(&self, registry: &Registry, token: Token) -> Result<(), ErrorImpl> {
+        let fd = self.pidfd.as_raw_fd();
+        registry
+            .register(&mut SourceFd(&fd), token, Interest::READABLE)
+            .map_err(ErrorImpl::PollInit)
+    }
+}
+
+/// A mount table to watch, see [`open_table`].
+pub(crate) struct OpenTable<M> {
+    pub(crate) table: Box<dyn MountTableSource<M>>,
+    /// The process to which the table belongs, when watching another process.
+    pub(crate) process: Option<WatchedProcess>,
+}
 
-        // register the timer to the epoll instance
-        if register {
-            let fd = timer.as_raw_fd();
-            let mut source = SourceFd(&fd);
-            poll.registry()
-                .register(&mut source, TIMER_TOKEN, Interest::READABLE)
-                .map_err(ErrorImpl::PollTimer)?;
-            log::trace!("timerfd registered");
+/// Opens the mount table to watch: the `source` if any, or a [`ProcMountTable`].
+pub(crate) fn open_table<M: MountEntry>(
+    source: Option<Box<dyn MountTableSource<M>>>,
+    location: Option<TableLocation>,
+) -> Result<OpenTable<M>, ErrorImpl> {
+    if let Some(table) = source {
+        return Ok(OpenTable {
+            table,
+            process: None,
+        });
+    }
+    let mut watched_process = None;
+    let table = match location {
+        None => ProcMountTable::<M>::new(),
+        Some(TableLocation::Path(path)) => ProcMountTable::open(path),
+        Some(TableLocation::Pid(pid)) => {
+            // Get the pidfd first: if the process exits and its pid is reused
+            // before we open its table, we'll still notice the exit.
+            let pidfd = pidfd_open(pid).map_err(|e| ErrorImpl::Pidfd(pid, e))?;
+            watched_process = Some(WatchedProcess { pid, pidfd });
+            ProcMountTable::for_pid(pid)
         }
-        // set the coalescing flag
-        self.coalescing = true;
-        Ok(())
+        Some(TableLocation::Namespace(ns)) => ProcMountTable::for_namespace(ns),
+    };
+    let table = table.map_err(|e| ErrorImpl::MountRead(ReadError::Io(e)))?;
+    Ok(OpenTable {
+        table: Box::new(table),
+        process: watched_process,
+    })
+}
+
+/// Opens a file descriptor that refers to the process `pid`.
+fn pidfd_open(pid: u32) -> std::io::Result<OwnedFd> {
+    // SAFETY: no pointer is involved
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
     }
+    // SAFETY: the fd has just been created, and nothing else owns it
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
 }
 
 /// Starts a background thread that uses [`mio::poll`] (backed by `epoll`) to detect changes to the mounted filesystem.
-fn watch_mounts<F: FnMut(MountEvent) -> WatchControl + Send + 'static>(
+pub(crate) fn watch_mounts<
+    M: MountEntry,
+    S,
+    F: FnMut(MountEvent<M>) -> Result<WatchControl, CallbackError> + Send + 'static,
+>(
+    config: MountWatcherBuilder<M, S>,
     callback: F,
-) -> Result<MountWatcher, ErrorImpl> {
-    // Open the file that contains info about the mounted filesystems.
-    let mut file =
-        File::open(PROC_MOUNTS_PATH).map_err(|e| ErrorImpl::MountRead(ReadError::Io(e)))?;
-    let fd = file.as_raw_fd();
-    let mut fd = SourceFd(&fd);
+) -> Result<MountWatcher<M>, ErrorImpl> {
+    let backing_path = resolve_backing_path::<M>(
+        config.backing_path,
+        config.table.as_ref(),
+        config.source.is_some(),
+    )?;
+
+    // Open the file that contains info about the mounted filesystems, unless another source is provided.
+    let OpenTable {
+        mut table,
+        process: watched_process,
+    } = open_table(config.source, config.table)?;
 
     // Prepare epoll.
     let mut poll = Poll::new().map_err(ErrorImpl::PollInit)?;
@@ -265,25 +1054,43 @@ fn watch_mounts<F: FnMut(MountEvent) -> WatchControl + Send + 'static>(
     // Create a mean to wake epoll from another thread.
     let stop_waker = Waker::new(poll.registry(), STOP_TOKEN).map_err(ErrorImpl::PollInit)?;
 
-    // According to `man proc_mounts`, a filesystem mount or unmount causes
-    // `poll` and `epoll_wait` to mark the file as having a PRIORITY event.
-    poll.registry()
-        .register(&mut fd, MOUNT_TOKEN, Interest::PRIORITY)
-        .map_err(ErrorImpl::PollInit)?;
+    register_source(table.as_ref(), poll.registry(), MOUNT_TOKEN).map_err(ErrorImpl::PollInit)?;
+    if let Some(process) = &watched_process {
+        process.register(poll.registry(), PROCESS_TOKEN)?;
+    }
+    let fstab = match config.fstab {
+        Some(path) => Some(FstabWatch::new(path)?),
+        None => None,
+    };
+    if let Some(fstab) = &fstab {
+        fstab.register(poll.registry(), FSTAB_TOKEN)?;
+    }
+
+    let snapshot = Arc::new(Mutex::new(MountSnapshot::empty()));
+    let shared_snapshot = snapshot.clone();
 
     // Declare the polling loop separately to handle errors in a nicer way.
     let poll_loop = move || -> Result<(), ErrorImpl> {
         let mut events = Events::with_capacity(8); // we don't expect many events
-        let mut state = State::new(callback);
+        let options = StateOptions {
+            filter: config.filter,
+            backing_path,
+            initial_event: config.initial_event,
+            error_policy: config.error_policy,
+            error_hook: config.error_hook,
+            catch_panics: config.catch_panics,
+            fstab,
+        };
+        let tokens = TimerTokens {
+            coalesce: TIMER_TOKEN,
+            retry: RETRY_TOKEN,
+        };
+        let mut state = State::new(options, tokens, shared_snapshot, callback);
 
         // While we were setting up epoll, some filesystems may have been mounted.
         // Check that here to avoid any miss.
-        match state.check_diff(&mut file, false, true)? {
-            WatchControl::Continue => (),
-            WatchControl::Stop => return Ok(()),
-            WatchControl::Coalesce { delay } => {
-                state.start_coalescing(delay, &poll)?;
-            }
+        if !state.handle_event(Trigger::Mount, &mut table, &poll)? {
+            return Ok(());
         }
 
         loop {
@@ -296,40 +1103,175 @@ fn watch_mounts<F: FnMut(MountEvent) -> WatchControl + Send + 'static>(
                 }
             }
 
-            // Call next() because we are not interested in each individual event.
             // If the timeout elapses, the event list is empty.
-            if let Some(event) = events.iter().next() {
-                log::debug!("event on /proc/mounts: {event:?}");
+            for event in events.iter() {
+                log::debug!("event: {event:?}");
 
                 // the stop_waker has been triggered, which means that we must stop now
                 if event.token() == STOP_TOKEN {
-                    break; // stop
+                    return Ok(());
+                }
+
+                // the watched process has exited, its mounts cannot change anymore
+                if event.token() == PROCESS_TOKEN {
+                    let pid = watched_process.as_ref().map_or(0, |p| p.pid);
+                    return Err(ErrorImpl::ProcessExited(pid));
+                }
+
+                // fstab has been modified, it will be read with the mount table
+                if event.token() == FSTAB_TOKEN {
+                    state.handle_fstab(&poll)?;
+                    continue;
                 }
 
                 // parse mount file and react to changes
-                let coalesced = event.token() == TIMER_TOKEN;
-                match state.check_diff(&mut file, coalesced, false)? {
-                    WatchControl::Continue => (),
-                    WatchControl::Stop => break,
-                    WatchControl::Coalesce { delay } => {
-                        state.start_coalescing(delay, &poll)?;
-                    }
+                let trigger = match event.token() {
+                    MOUNT_TOKEN => Trigger::Mount,
+                    TIMER_TOKEN => Trigger::Timer,
+                    RETRY_TOKEN => Trigger::Retry,
+                    token => unreachable!("unexpected token {token:?}"),
+                };
+                if !state.handle_event(trigger, &mut table, &poll)? {
+                    return Ok(());
                 }
             }
         }
-        Ok(())
     };
 
     // Spawn a thread.
-    let thread_handle = std::thread::spawn(move || {
-        if let Err(e) = poll_loop() {
-            log::error!("error in polling loop: {e:?}");
-        }
-    });
+    let mut thread_builder = std::thread::Builder::new();
+    if let Some(name) = config.thread_name {
+        thread_builder = thread_builder.name(name);
+    }
+    if let Some(size) = config.stack_size {
+        thread_builder = thread_builder.stack_size(size);
+    }
+    let thread_handle = thread_builder
+        .spawn(move || {
+            poll_loop().map_err(|e| {
+                log::error!("error in polling loop: {e:?}");
+                WatchError(e)
+            })
+        })
+        .map_err(ErrorImpl::ThreadSpawn)?;
 
     // Return a structure that will stop the polling when dropped.
     Ok(MountWatcher {
         thread_handle: Some(thread_handle),
         stop_waker: Arc::new(stop_waker),
+        snapshot,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        os::fd::AsRawFd,
+        sync::{mpsc, Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use pretty_assertions::assert_eq;
+
+    use super::{diff_mounts, ErrorPolicy, JoinError, MountChange};
+    use crate::{test_util::mount, MountWatcher, WatchControl};
+
+    #[test]
+    fn diff_remount() {
+        let known = HashSet::from([
+            mount("/dev/sda1 /data ext4 rw,relatime 0 0"),
+            mount("tmpfs /tmp tmpfs rw 0 0"),
+        ]);
+        let current = HashSet::from([
+            mount("/dev/sda1 /data ext4 ro,relatime 0 0"),
+            mount("tmpfs /run tmpfs rw 0 0"),
+        ]);
+        let event = diff_mounts(&known, &current, false, false).unwrap();
+        assert_eq!(event.mounted, vec![mount("tmpfs /run tmpfs rw 0 0")]);
+        assert_eq!(event.unmounted, vec![mount("tmpfs /tmp tmpfs rw 0 0")]);
+        assert_eq!(
+            event.changed,
+            vec![MountChange {
+                old: mount("/dev/sda1 /data ext4 rw,relatime 0 0"),
+                new: mount("/dev/sda1 /data ext4 ro,relatime 0 0"),
+            }]
+        );
+    }
+
+    #[test]
+    fn diff_nothing() {
+        let known = HashSet::from([mount("tmpfs /tmp tmpfs rw 0 0")]);
+        assert!(diff_mounts(&known, &known.clone(), false, false).is_none());
+    }
+
+    /// Returns a pipe and its path. A pipe supports epoll but cannot be rewound,
+    /// thus reading it as a mount table always fails.
+    fn unreadable_table() -> (mio::unix::pipe::Sender, mio::unix::pipe::Receiver, String) {
+        let (tx, rx) = mio::unix::pipe::new().unwrap();
+        let path = format!("/proc/self/fd/{}", rx.as_raw_fd());
+        (tx, rx, path)
+    }
+
+    #[test]
+    fn read_error_stops() {
+        let (_tx, _rx, path) = unreadable_table();
+        let watch = MountWatcher::builder()
+            .table_path(path)
+            .build(|_| WatchControl::Continue)
+            .unwrap();
+        match watch.join() {
+            Err(JoinError::Watch(e)) => assert!(e.as_read_error().is_some()),
+            res => panic!("unexpected result {res:?}"),
+        }
+    }
+
+    #[test]
+    fn read_error_retry() {
+        let (_tx, _rx, path) = unreadable_table();
+        let (tx, rx) = mpsc::channel();
+        let mut errors = Vec::new();
+        let watch = MountWatcher::builder()
+            .table_path(path)
+            .error_policy(ErrorPolicy::Retry {
+                initial_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(40),
+            })
+            .on_error(move |_| {
+                errors.push(Instant::now());
+                if errors.len() == 5 {
+                    tx.send(errors.clone()).unwrap();
+                }
+            })
+            .build(|_| WatchControl::Continue)
+            .unwrap();
+        let errors = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        watch.stop().unwrap();
+        watch.join().unwrap();
+        // The timers never fire early, hence each interval is at least as long as the delay.
+        let intervals: Vec<_> = errors.windows(2).map(|w| w[1] - w[0]).collect();
+        for (interval, delay) in intervals.iter().zip([10, 20, 40, 40]) {
+            assert!(
+                *interval >= Duration::from_millis(delay),
+                "intervals: {intervals:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn read_error_skip() {
+        let (_tx, _rx, path) = unreadable_table();
+        let errors = Arc::new(Mutex::new(0));
+        let errors2 = errors.clone();
+        let watch = MountWatcher::builder()
+            .table_path(path)
+            .error_policy(ErrorPolicy::Skip)
+            .on_error(move |_| *errors2.lock().unwrap() += 1)
+            .build(|_| WatchControl::Continue)
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        watch.stop().unwrap();
+        watch.join().unwrap();
+        assert_eq!(*errors.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,43 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)] // each test file uses some of them
+
+use std::{sync::mpsc, time::Duration};
+
+use mount_watcher::{
+    channel::MountEventReceiver,
+    hub::{MountWatchHub, Subscription},
+    mount::LinuxMount,
+    table::FakeMountTable,
+    MountEvent, MountWatcherBuilder, WatchControl,
+};
+
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn mount(line: &str) -> LinuxMount {
+    LinuxMount::parse(line).unwrap()
+}
+
+/// Watches the fake `table`, and receives the initial event.
+pub fn start(builder: MountWatcherBuilder, table: &FakeMountTable) -> MountEventReceiver {
+    let events = builder.source(table.clone()).build_channel().unwrap();
+    let initial = events.recv_timeout(TIMEOUT).unwrap();
+    assert!(initial.initial);
+    events
+}
+
+/// Like [`start`], with a subscriber of the `hub`.
+pub fn subscribe(
+    hub: &MountWatchHub,
+    builder: MountWatcherBuilder,
+    table: &FakeMountTable,
+) -> (Subscription, mpsc::Receiver<MountEvent>) {
+    let (tx, rx) = mpsc::channel();
+    let subscription = hub
+        .subscribe(builder.source(table.clone()), move |event| {
+            let _ = tx.send(event);
+            WatchControl::Continue
+        })
+        .unwrap();
+    assert!(rx.recv_timeout(TIMEOUT).unwrap().initial);
+    (subscription, rx)
+}
@@ -0,0 +1,184 @@
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+
+use mount_watcher::{
+    callback::CoalesceInitial, filter::MountFilter, table::FakeMountTable, MountChange,
+    MountWatcher, WatchControl,
+};
+use pretty_assertions::assert_eq;
+
+mod common;
+use common::{mount, start, TIMEOUT};
+
+/*
+These tests use a fake mount table to check the state machine of the watcher, without mounting anything.
+*/
+
+#[test]
+fn mount_remount_unmount() {
+    let table = FakeMountTable::new().unwrap();
+    table.mount(mount("tmpfs /tmp tmpfs rw 0 0"));
+    let events = start(MountWatcher::builder(), &table);
+
+    table.mount(mount("/dev/sdb1 /mnt/usb vfat rw 0 0"));
+    table.notify();
+    let event = events.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(event.mounted, vec![mount("/dev/sdb1 /mnt/usb vfat rw 0 0")]);
+    assert!(event.unmounted.is_empty());
+    assert!(!event.initial);
+
+    table.unmount(&mount("/dev/sdb1 /mnt/usb vfat rw 0 0"));
+    table.mount(mount("/dev/sdb1 /mnt/usb vfat ro 0 0"));
+    table.notify();
+    let event = events.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(
+        event.changed,
+        vec![MountChange {
+            old: mount("/dev/sdb1 /mnt/usb vfat rw 0 0"),
+            new: mount("/dev/sdb1 /mnt/usb vfat ro 0 0"),
+        }]
+    );
+
+    table.unmount(&mount("/dev/sdb1 /mnt/usb vfat ro 0 0"));
+    table.notify();
+    let event = events.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(
+        event.unmounted,
+        vec![mount("/dev/sdb1 /mnt/usb vfat ro 0 0")]
+    );
+}
+
+#[test]
+fn coalesce() {
+    let table = FakeMountTable::new().unwrap();
+    let builder = MountWatcher::builder()
+        .coalesce(Duration::from_millis(200), CoalesceInitial::PassImmediately);
+    let events = start(builder, &table);
+
+    table.mount(mount("/dev/sdb1 /mnt/a ext4 rw 0 0"));
+    table.notify();
+    std::thread::sleep(Duration::from_millis(20));
+    table.mount(mount("/dev/sdc1 /mnt/b ext4 rw 0 0"));
+    table.notify();
+
+    let mut event = events.recv_timeout(TIMEOUT).unwrap();
+    assert!(event.coalesced);
+    event
+        .mounted
+        .sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+    assert_eq!(
+        event.mounted,
+        vec![
+            mount("/dev/sdb1 /mnt/a ext4 rw 0 0"),
+            mount("/dev/sdc1 /mnt/b ext4 rw 0 0")
+        ]
+    );
+}
+
+#[test]
+fn filter() {
+    let table = FakeMountTable::new().unwrap();
+    let builder = MountWatcher::builder().filter(MountFilter::new().fs_type("ext4"));
+    let events = start(builder, &table);
+
+    table.mount(mount("tmpfs /run/user tmpfs rw 0 0"));
+    table.notify();
+    assert!(matches!(
+        events.recv_timeout(Duration::from_millis(200)),
+        Err(RecvTimeoutError::Timeout)
+    ));
+
+    table.mount(mount("/dev/sdb1 /data ext4 rw 0 0"));
+    table.notify();
+    let event = events.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(event.mounted, vec![mount("/dev/sdb1 /data ext4 rw 0 0")]);
+}
+
+#[test]
+fn stop_in_callback() {
+    let table = FakeMountTable::new().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let watch = MountWatcher::builder()
+        .source(table.clone())
+        .build(move |event| {
+            tx.send(event.initial).unwrap();
+            if event.initial {
+                WatchControl::Continue
+            } else {
+                WatchControl::Stop
+            }
+        })
+        .unwrap();
+    assert!(rx.recv_timeout(TIMEOUT).unwrap());
+
+    table.mount(mount("tmpfs /tmp tmpfs rw 0 0"));
+    table.notify();
+    watch.join().unwrap();
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![false]);
+}
+
+#[test]
+fn backing_mount_replaced() {
+    let table = FakeMountTable::new().unwrap();
+    table.mount(mount("/dev/sda1 / ext4 rw 0 0"));
+    table.mount(mount("tmpfs /tmp tmpfs rw 0 0"));
+    let events = start(MountWatcher::builder().backing_mount_of("/proc"), &table);
+
+    // mounted over the path: the previous mount is still there
+    table.mount(mount("proc /proc proc rw 0 0"));
+    table.notify();
+    let event = events.recv_timeout(TIMEOUT).unwrap();
+    assert!(event.mounted.is_empty() && event.unmounted.is_empty());
+    assert_eq!(
+        event.changed,
+        vec![MountChange {
+            old: mount("/dev/sda1 / ext4 rw 0 0"),
+            new: mount("proc /proc proc rw 0 0"),
+        }]
+    );
+
+    // really unmounted
+    table.unmount(&mount("proc /proc proc rw 0 0"));
+    table.notify();
+    let event = events.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(event.unmounted, vec![mount("proc /proc proc rw 0 0")]);
+    assert_eq!(event.mounted, vec![mount("/dev/sda1 / ext4 rw 0 0")]);
+    assert!(event.changed.is_empty());
+}
+
+#[test]
+fn fstab_coalesced_with_mounts() {
+    let dir = std::env::temp_dir().join(format!("mount-watcher-fake-fstab-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fstab");
+    std::fs::write(&path, "/dev/sda1 / ext4 defaults 0 1\n").unwrap();
+
+    let table = FakeMountTable::new().unwrap();
+    let builder = MountWatcher::builder()
+        .watch_fstab_file(&path)
+        .coalesce(Duration::from_millis(200), CoalesceInitial::PassImmediately);
+    let events = start(builder, &table);
+
+    std::fs::write(
+        &path,
+        "/dev/sda1 / ext4 defaults 0 1\n/dev/sdb1 /mnt/a ext4 defaults 0 2\n",
+    )
+    .unwrap();
+    // fstab has been read, and the callback is coalescing
+    std::thread::sleep(Duration::from_millis(150));
+    table.mount(mount("/dev/sdb1 /mnt/a ext4 rw 0 0"));
+    table.notify();
+
+    let event = events.recv_timeout(TIMEOUT).unwrap();
+    assert!(event.coalesced);
+    assert_eq!(event.mounted, vec![mount("/dev/sdb1 /mnt/a ext4 rw 0 0")]);
+    let fstab = event.fstab.unwrap();
+    assert_eq!(fstab.added.len(), 1);
+    assert_eq!(fstab.added[0].spec, "/dev/sdb1");
+    assert!(fstab.removed.is_empty() && fstab.changed.is_empty());
+
+    assert!(events.recv_timeout(Duration::from_millis(300)).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,125 @@
+use std::{
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use mount_watcher::{
+    callback::CoalesceInitial, hub::MountWatchHub, table::FakeMountTable, MountWatcher,
+    WatchControl,
+};
+use pretty_assertions::assert_eq;
+
+mod common;
+use common::{mount, subscribe, TIMEOUT};
+
+#[test]
+fn independent_subscribers() {
+    let hub = MountWatchHub::new().unwrap();
+    let table_a = FakeMountTable::new().unwrap();
+    let table_b = FakeMountTable::new().unwrap();
+    let (sub_a, events_a) = subscribe(&hub, MountWatcher::builder(), &table_a);
+    let builder_b = MountWatcher::builder()
+        .coalesce(Duration::from_millis(100), CoalesceInitial::PassImmediately);
+    let (sub_b, events_b) = subscribe(&hub, builder_b, &table_b);
+
+    table_a.mount(mount("/dev/sdb1 /mnt/a ext4 rw 0 0"));
+    table_a.notify();
+    let event = events_a.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(event.mounted, vec![mount("/dev/sdb1 /mnt/a ext4 rw 0 0")]);
+    assert!(!event.coalesced);
+
+    table_b.mount(mount("/dev/sdc1 /mnt/b ext4 rw 0 0"));
+    table_b.notify();
+    let event = events_b.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(event.mounted, vec![mount("/dev/sdc1 /mnt/b ext4 rw 0 0")]);
+    assert!(event.coalesced);
+    assert!(events_a.try_recv().is_err());
+
+    // stopping a subscriber does not affect the other one
+    sub_a.stop().unwrap();
+    sub_a.join().unwrap();
+    table_b.unmount(&mount("/dev/sdc1 /mnt/b ext4 rw 0 0"));
+    table_b.notify();
+    let event = events_b.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(event.unmounted, vec![mount("/dev/sdc1 /mnt/b ext4 rw 0 0")]);
+
+    hub.stop().unwrap();
+    sub_b.join().unwrap();
+    hub.join().unwrap();
+}
+
+#[test]
+fn stop_in_callback() {
+    let hub = MountWatchHub::new().unwrap();
+    let table = FakeMountTable::new().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let subscription = hub
+        .subscribe(
+            MountWatcher::builder().source(table.clone()),
+            move |event| {
+                tx.send(event.initial).unwrap();
+                if event.initial {
+                    WatchControl::Continue
+                } else {
+                    WatchControl::Stop
+                }
+            },
+        )
+        .unwrap();
+    assert!(rx.recv_timeout(TIMEOUT).unwrap());
+    table.mount(mount("tmpfs /tmp tmpfs rw 0 0"));
+    table.notify();
+    subscription.join().unwrap();
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![false]);
+}
+
+#[test]
+fn shared_table() {
+    let hub = MountWatchHub::new().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let subscriptions: Vec<_> = (0..3)
+        .map(|i| {
+            let tx = tx.clone();
+            hub.subscribe(MountWatcher::builder(), move |event| {
+                tx.send((i, event.mounted.len())).unwrap();
+                WatchControl::Continue
+            })
+            .unwrap()
+        })
+        .collect();
+    let mut initial: Vec<_> = (0..3).map(|_| rx.recv_timeout(TIMEOUT).unwrap()).collect();
+    initial.sort();
+    let count = initial[0].1;
+    assert_eq!(initial, vec![(0, count), (1, count), (2, count)]);
+    drop(subscriptions);
+}
+
+#[test]
+fn wait_in_callback() {
+    let hub = Arc::new(MountWatchHub::new().unwrap());
+    let table = FakeMountTable::new().unwrap();
+    let (subscription, _events) = subscribe(&hub, MountWatcher::builder(), &table);
+    let other_table = FakeMountTable::new().unwrap();
+    let mut subscription = Some(subscription);
+    let hub2 = Arc::clone(&hub);
+    let (tx, rx) = mpsc::channel();
+    let other = hub
+        .subscribe(MountWatcher::builder().source(other_table), move |_| {
+            // both would wait for the hub thread, which is running this callback
+            let subscribed = hub2.subscribe(MountWatcher::builder(), |_| WatchControl::Continue);
+            let joined = subscription.take().unwrap().join();
+            tx.send((subscribed.is_err(), joined.is_err())).unwrap();
+            WatchControl::Stop
+        })
+        .unwrap();
+    assert_eq!(rx.recv_timeout(TIMEOUT).unwrap(), (true, true));
+    other.join().unwrap();
+}
+
+#[test]
+fn watch_fstab_unsupported() {
+    let hub = MountWatchHub::new().unwrap();
+    let table = FakeMountTable::new().unwrap();
+    let builder = MountWatcher::builder().source(table).watch_fstab();
+    assert!(hub.subscribe(builder, |_| WatchControl::Continue).is_err());
+}
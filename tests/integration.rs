@@ -84,7 +84,15 @@ fn print_event(event: MountEvent) {
             .map(|m| format!("{m:?}"))
             .collect::<Vec<String>>()
             .join("\n\t")
-            .to_string()
+    );
+    println!(
+        "changed:\n\t{}",
+        event
+            .changed
+            .iter()
+            .map(|c| format!("{:?} => {:?}", c.old, c.new))
+            .collect::<Vec<String>>()
+            .join("\n\t")
     );
     println!(
         "unmounted:\n\t{}",
@@ -94,6 +102,5 @@ fn print_event(event: MountEvent) {
             .map(|m| format!("{m:?}"))
             .collect::<Vec<String>>()
             .join("\n\t")
-            .to_string()
     );
 }